@@ -0,0 +1,77 @@
+//! Updating the `GlobalTransform`s of large numbers of grid entities with child hierarchies.
+
+use bevy::{core::CorePlugin, prelude::*};
+use big_space::{FloatingOrigin, FloatingOriginPlugin, GridCell};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const SHIPS: i64 = 100_000;
+const PARTS_PER_SHIP: usize = 8;
+
+/// Spawns a grid of ships, each with a few layers of child parts, and runs the first update.
+fn setup() -> (App, Entity) {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin::default())
+        .add_plugin(FloatingOriginPlugin::<i64>::default());
+
+    let origin = app
+        .world
+        .spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ))
+        .id();
+    for i in 0..SHIPS {
+        let cell = GridCell::<i64>::new(i % 100, i / 100 % 100, i / 10_000);
+        let ship = app.world.spawn((TransformBundle::default(), cell)).id();
+        let mut parent = ship;
+        for part in 0..PARTS_PER_SHIP {
+            let transform = Transform::from_xyz(part as f32, 0.0, 0.0);
+            let child = app
+                .world
+                .spawn(TransformBundle::from_transform(transform))
+                .id();
+            app.world.entity_mut(parent).push_children(&[child]);
+            // Alternate between wide and deep hierarchies.
+            if part % 2 == 1 {
+                parent = child;
+            }
+        }
+    }
+    app.update();
+    (app, origin)
+}
+
+fn propagation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("propagation");
+    group.sample_size(10);
+
+    let (mut app, _) = setup();
+    group.bench_function("unchanged", |b| b.iter(|| app.update()));
+
+    let (mut app, origin) = setup();
+    group.bench_function("origin moved", |b| {
+        b.iter(|| {
+            app.world.get_mut::<GridCell<i64>>(origin).unwrap().x += 1;
+            app.update();
+        })
+    });
+
+    let (mut app, _) = setup();
+    let mut ships = app
+        .world
+        .query_filtered::<&mut Transform, (With<GridCell<i64>>, Without<FloatingOrigin>)>();
+    group.bench_function("every ship moved", |b| {
+        b.iter(|| {
+            for mut transform in ships.iter_mut(&mut app.world) {
+                transform.translation.x += 1.0;
+            }
+            app.update();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, propagation);
+criterion_main!(benches);
@@ -1,12 +1,14 @@
 use bevy::prelude::*;
-use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell, OriginMode};
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins.build().disable::<TransformPlugin>())
+        .add_plugins(DefaultPlugins)
         .add_plugin(big_space::FloatingOriginPlugin::<i64>::default())
         .add_plugin(big_space::debug::FloatingOriginDebugPlugin::<i64>::default())
-        .insert_resource(FloatingOriginSettings::new(1.0, 0.01))
+        .insert_resource(
+            FloatingOriginSettings::new(1.0, 0.01).with_origin_mode(OriginMode::CameraRelative),
+        )
         .insert_resource(ClearColor(Color::BLACK))
         .add_startup_system(setup)
         .add_system(movement)
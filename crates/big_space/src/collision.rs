@@ -0,0 +1,477 @@
+//! Collision detection between grid entities.
+//!
+//! Add the [`CollisionPlugin`] and a [`Collider`] to grid entities to receive a [`Collision`] event
+//! for every pair of overlapping colliders each frame. Candidate pairs are found with the
+//! [`GridIndex`], so small colliders are only tested against entities in the same or neighboring
+//! cells, and every test is done with `f64` math relative to one of the pair, so it is as precise
+//! at the edge of the grid as at the origin.
+//!
+//! Collisions are only detected, not resolved. Like the [`GridIndex`], only entities in the root
+//! grid are tested.
+//!
+//! With [`CollisionPlugin::fixed_timestep`], collisions are instead detected after every step of
+//! the [`FixedUpdateStage`], between the simulated positions of
+//! [`Interpolated`](crate::fixed_timestep::Interpolated) entities rather than where they are
+//! rendered. The [`GridIndex`] is updated after every step too, before [`detect_collisions`]. Read
+//! the [`Collision`] events in the same stage, after [`detect_collisions`], as the stage may not
+//! run at all in some frames.
+
+use std::marker::PhantomData;
+
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+};
+
+use crate::{
+    fixed_timestep::{FixedTimestepPlugin, FixedUpdateStage},
+    grid_index::{update_grid_index, GridIndex},
+    precision::GridPrecision,
+    FloatingOriginSettings, FloatingOriginSystem, GridCell,
+};
+
+/// Adds collision detection between grid entities with a [`Collider`]. See the
+/// [module docs](crate::collision).
+#[derive(Default)]
+pub struct CollisionPlugin<P: GridPrecision> {
+    /// Detect collisions after every step of the [`FixedUpdateStage`], instead of once per frame in
+    /// [`CoreStage::PostUpdate`]. The [`FixedTimestepPlugin`] must be added first.
+    pub fixed_timestep: bool,
+    pub phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision> Plugin for CollisionPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Collider>().add_event::<Collision>();
+        if self.fixed_timestep {
+            assert!(
+                app.is_plugin_added::<FixedTimestepPlugin<P>>(),
+                "The FixedTimestepPlugin must be added before the CollisionPlugin"
+            );
+            app.add_system_to_stage(
+                FixedUpdateStage,
+                update_grid_index::<P>
+                    .label(FloatingOriginSystem::UpdateGridIndex)
+                    .after(FloatingOriginSystem::Integrate),
+            )
+            .add_system_to_stage(
+                FixedUpdateStage,
+                detect_collisions::<P>.after(FloatingOriginSystem::UpdateGridIndex),
+            );
+        } else {
+            app.add_system_to_stage(
+                CoreStage::PostUpdate,
+                detect_collisions::<P>.after(FloatingOriginSystem::UpdateGridIndex),
+            );
+        }
+    }
+}
+
+/// The shape of a grid entity, centered on its translation. Shapes are rotated and scaled with the
+/// entity's [`Transform`]; spheres are scaled by the largest axis of the scale.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Collider {
+    Sphere { radius: f32 },
+    Box { half_extents: Vec3 },
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self::Sphere { radius: 0.5 }
+    }
+}
+
+impl Collider {
+    pub fn sphere(radius: f32) -> Self {
+        Self::Sphere { radius }
+    }
+
+    pub fn cuboid(half_extents: Vec3) -> Self {
+        Self::Box { half_extents }
+    }
+
+    /// The radius of a sphere around the entity that contains the whole collider.
+    pub fn bounding_radius(&self, transform: &Transform) -> f64 {
+        match self {
+            Self::Sphere { radius } => (*radius * transform.scale.abs().max_element()) as f64,
+            Self::Box { half_extents } => (*half_extents * transform.scale).as_dvec3().length(),
+        }
+    }
+}
+
+/// Sent by [`detect_collisions`] for every pair of overlapping [`Collider`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    pub a: Entity,
+    pub b: Entity,
+    /// The direction `b` needs to move in to stop overlapping `a`.
+    pub normal: DVec3,
+    /// How far `b` needs to move along `normal` to stop overlapping `a`.
+    pub depth: f64,
+}
+
+/// A collider placed relative to another entity, in `f64`.
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Sphere {
+        radius: f64,
+    },
+    Box {
+        half_extents: DVec3,
+        rotation: DQuat,
+    },
+}
+
+impl Shape {
+    fn new(collider: &Collider, transform: &Transform) -> Self {
+        match *collider {
+            Collider::Sphere { .. } => Shape::Sphere {
+                radius: collider.bounding_radius(transform),
+            },
+            Collider::Box { half_extents } => Shape::Box {
+                half_extents: (half_extents * transform.scale).abs().as_dvec3(),
+                rotation: transform.rotation.as_f64(),
+            },
+        }
+    }
+}
+
+/// Tests whether `a`, centered at zero, overlaps `b`, centered at `offset`. The normal points from
+/// `a` towards `b`.
+fn contact(a: Shape, b: Shape, offset: DVec3) -> Option<(DVec3, f64)> {
+    match (a, b) {
+        (Shape::Sphere { radius: a }, Shape::Sphere { radius: b }) => {
+            let distance = offset.length();
+            let depth = a + b - distance;
+            (depth >= 0.0).then(|| (offset.try_normalize().unwrap_or(DVec3::X), depth))
+        }
+        (
+            Shape::Sphere { radius },
+            Shape::Box {
+                half_extents,
+                rotation,
+            },
+        ) => sphere_box(radius, half_extents, rotation, offset),
+        (
+            Shape::Box {
+                half_extents,
+                rotation,
+            },
+            Shape::Sphere { radius },
+        ) => sphere_box(radius, half_extents, rotation, -offset)
+            .map(|(normal, depth)| (-normal, depth)),
+        (
+            Shape::Box {
+                half_extents: a_extents,
+                rotation: a_rotation,
+            },
+            Shape::Box {
+                half_extents: b_extents,
+                rotation: b_rotation,
+            },
+        ) => box_box(a_extents, a_rotation, b_extents, b_rotation, offset),
+    }
+}
+
+/// Tests a sphere at zero against a box at `offset`, with the normal pointing towards the box.
+fn sphere_box(
+    radius: f64,
+    half_extents: DVec3,
+    rotation: DQuat,
+    offset: DVec3,
+) -> Option<(DVec3, f64)> {
+    // The center of the sphere, in the local space of the box.
+    let center = rotation.inverse() * -offset;
+    let closest = center.clamp(-half_extents, half_extents);
+    if closest != center {
+        let outside = center - closest;
+        let distance = outside.length();
+        return (distance <= radius).then(|| (rotation * -outside / distance, radius - distance));
+    }
+    // The center is inside the box, so push it out through the nearest face.
+    let to_face = half_extents - center.abs();
+    let axis = if to_face.x <= to_face.y && to_face.x <= to_face.z {
+        DVec3::X
+    } else if to_face.y <= to_face.z {
+        DVec3::Y
+    } else {
+        DVec3::Z
+    };
+    let face = axis * center.signum();
+    Some((rotation * -face, radius + to_face.dot(axis)))
+}
+
+/// Tests a box at zero against a box at `offset` with the separating axis theorem, with the normal
+/// pointing towards the second box.
+fn box_box(
+    a_extents: DVec3,
+    a_rotation: DQuat,
+    b_extents: DVec3,
+    b_rotation: DQuat,
+    offset: DVec3,
+) -> Option<(DVec3, f64)> {
+    let a_axes = [DVec3::X, DVec3::Y, DVec3::Z].map(|axis| a_rotation * axis);
+    let b_axes = [DVec3::X, DVec3::Y, DVec3::Z].map(|axis| b_rotation * axis);
+    let project = |axes: &[DVec3; 3], extents: DVec3, axis: DVec3| {
+        extents.x * axes[0].dot(axis).abs()
+            + extents.y * axes[1].dot(axis).abs()
+            + extents.z * axes[2].dot(axis).abs()
+    };
+
+    let edge_axes = a_axes
+        .iter()
+        .flat_map(|a| b_axes.iter().map(move |b| a.cross(*b)));
+    let mut best: Option<(DVec3, f64)> = None;
+    for axis in a_axes.into_iter().chain(b_axes).chain(edge_axes) {
+        // Edges that are parallel don't give a separating axis.
+        let axis = match axis.try_normalize() {
+            Some(axis) => axis,
+            None => continue,
+        };
+        let distance = offset.dot(axis);
+        let depth =
+            project(&a_axes, a_extents, axis) + project(&b_axes, b_extents, axis) - distance.abs();
+        if depth < 0.0 {
+            return None;
+        }
+        if !matches!(best, Some((_, best_depth)) if best_depth <= depth) {
+            let normal = if distance < 0.0 { -axis } else { axis };
+            best = Some((normal, depth));
+        }
+    }
+    best
+}
+
+/// Sends a [`Collision`] for every pair of overlapping [`Collider`]s in the root grid.
+///
+/// Each pair is tested once, from the entity with the larger collider, which searches the cells
+/// its collider could reach a smaller one in.
+pub fn detect_collisions<P: GridPrecision>(
+    settings: Res<FloatingOriginSettings>,
+    index: Res<GridIndex<P>>,
+    colliders: Query<(Entity, &Collider, &GridCell<P>, &Transform)>,
+    mut collisions: EventWriter<Collision>,
+) {
+    let edge_length = settings.grid_edge_length();
+    let max_offset = 2.0 * settings.maximum_distance_from_origin();
+
+    for (a, a_collider, a_cell, a_transform) in &colliders {
+        if index.cell_of(a).is_none() {
+            continue;
+        }
+        let a_radius = a_collider.bounding_radius(a_transform);
+        let cell_radius = ((2.0 * a_radius + max_offset) / edge_length)
+            .ceil()
+            .min(u32::MAX as f64) as u32;
+        let a_shape = Shape::new(a_collider, a_transform);
+
+        for b in index.entities_within(*a_cell, cell_radius) {
+            let (b, b_collider, b_cell, b_transform) = match colliders.get(b) {
+                Ok(collider) => collider,
+                Err(_) => continue,
+            };
+            let b_radius = b_collider.bounding_radius(b_transform);
+            if (b_radius, b) >= (a_radius, a) {
+                continue;
+            }
+            let offset = settings.grid_offset_double(a_cell, b_cell)
+                + b_transform.translation.as_dvec3()
+                - a_transform.translation.as_dvec3();
+            if offset.length() > a_radius + b_radius {
+                continue;
+            }
+            if let Some((normal, depth)) =
+                contact(a_shape, Shape::new(b_collider, b_transform), offset)
+            {
+                collisions.send(Collision {
+                    a,
+                    b,
+                    normal,
+                    depth,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixed_timestep::Interpolated, kinematics::GridVelocity, tests::step_app, FloatingOrigin,
+        FloatingOriginPlugin,
+    };
+
+    const TOLERANCE: f64 = 1e-9;
+
+    fn assert_contact(contact: Option<(DVec3, f64)>, normal: DVec3, depth: f64) {
+        let (actual_normal, actual_depth) = contact.expect("no contact");
+        assert!(
+            (actual_normal - normal).length() < TOLERANCE
+                && (actual_depth - depth).abs() < TOLERANCE,
+            "expected {normal} and {depth}, found {actual_normal} and {actual_depth}"
+        );
+    }
+
+    #[test]
+    fn shapes() {
+        let sphere = Shape::Sphere { radius: 1.0 };
+        let cube = Shape::Box {
+            half_extents: DVec3::ONE,
+            rotation: DQuat::IDENTITY,
+        };
+        let diamond = Shape::Box {
+            half_extents: DVec3::ONE,
+            rotation: DQuat::from_rotation_z(std::f64::consts::FRAC_PI_4),
+        };
+
+        assert_contact(
+            contact(sphere, sphere, DVec3::new(0.0, 1.5, 0.0)),
+            DVec3::Y,
+            0.5,
+        );
+        assert_eq!(contact(sphere, sphere, DVec3::new(0.0, 2.5, 0.0)), None);
+
+        assert_contact(
+            contact(sphere, cube, DVec3::new(-1.5, 0.0, 0.0)),
+            DVec3::NEG_X,
+            0.5,
+        );
+        assert_contact(
+            contact(cube, sphere, DVec3::new(0.0, 0.0, 1.75)),
+            DVec3::Z,
+            0.25,
+        );
+        // Inside the box, the sphere is pushed out through the nearest face.
+        assert_contact(
+            contact(cube, sphere, DVec3::new(0.0, 0.0, 0.5)),
+            DVec3::Z,
+            1.5,
+        );
+        // Past the corner of the box, the sphere misses.
+        assert_eq!(contact(sphere, cube, DVec3::new(1.8, 1.8, 0.0)), None);
+
+        assert_contact(
+            contact(cube, cube, DVec3::new(1.5, 0.1, 0.0)),
+            DVec3::X,
+            0.5,
+        );
+        let corner = 2f64.sqrt();
+        assert_contact(
+            contact(cube, diamond, DVec3::new(0.0, 0.9 + corner, 0.0)),
+            DVec3::Y,
+            0.1,
+        );
+        assert_eq!(
+            contact(cube, diamond, DVec3::new(0.0, 1.1 + corner, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn collision_events() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default())
+            .add_plugin(CollisionPlugin::<i64>::default());
+        let settings = FloatingOriginSettings::default();
+        let edge = settings.grid_edge_length() as f32;
+
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let far = GridCell::new(i64::MAX, 0, 0);
+        // Two spheres touching across a cell boundary, at the edge of the grid.
+        let a = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(edge / 2.0 - 0.5, 0.0, 0.0)),
+                far - GridCell::new(1, 0, 0),
+                Collider::sphere(1.0),
+            ))
+            .id();
+        let b = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(-edge / 2.0 + 0.25, 0.0, 0.0)),
+                far,
+                Collider::sphere(1.0),
+            ))
+            .id();
+        // A larger box nearby that doesn't touch either.
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, 10.0, 0.0)),
+            far,
+            Collider::cuboid(Vec3::splat(5.0)),
+        ));
+        app.update();
+
+        let events = app.world.resource::<Events<Collision>>();
+        let collisions = events
+            .get_reader()
+            .iter(events)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(collisions.len(), 1);
+        let collision = collisions[0];
+        let (normal, depth) = if collision.a == a {
+            assert_eq!(collision.b, b);
+            (DVec3::X, 1.25)
+        } else {
+            assert_eq!((collision.a, collision.b), (b, a));
+            (DVec3::NEG_X, 1.25)
+        };
+        assert_contact(Some((collision.normal, collision.depth)), normal, depth);
+    }
+
+    #[test]
+    fn fixed_timestep_collisions() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64> {
+            settings: FloatingOriginSettings::new(1.0, 0.0),
+            ..default()
+        })
+        .add_plugin(FixedTimestepPlugin::<i64> {
+            timestep: 0.1,
+            ..default()
+        })
+        .add_plugin(CollisionPlugin::<i64> {
+            fixed_timestep: true,
+            ..default()
+        });
+        // Spawned first, so the pair is tested from the other sphere, which only finds this one if
+        // the index knows about the cells it crossed since the last frame.
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(-0.4, 0.0, 0.0)),
+            GridCell::<i64>::new(8, 0, 0),
+            GridVelocity(DVec3::new(-30.0, 0.0, 0.0)),
+            Interpolated::<i64>::default(),
+            Collider::sphere(1.0),
+        ));
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+            Collider::sphere(1.0),
+        ));
+        let mut step = |seconds| {
+            step_app(&mut app, seconds);
+            let events = app.world.resource::<Events<Collision>>();
+            events
+                .get_reader()
+                .iter(events)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert!(step(0.0).is_empty());
+
+        // After two steps, three cells each, the spheres overlap, but where they are rendered, half
+        // way between the last two steps, they don't.
+        let collisions = step(0.25);
+        assert_eq!(collisions.len(), 1);
+        assert!((collisions[0].depth - 0.4).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,271 @@
+//! Commands for working with grid entities and the floating origin.
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::system::{BoxedSystem, Command, EntityCommands, IntoSystem},
+    math::DVec3,
+    prelude::*,
+};
+
+use crate::{
+    fixed_point::FixedTranslation, precision::GridPrecision,
+    reference_frame::update_reference_frames, transform_propagate_system, update_global_from_grid,
+    FloatingOrigin, FloatingOriginSettings, GridCell,
+};
+
+/// Places an entity at an absolute position, computing its [`GridCell`] and the translation of its
+/// [`Transform`] with [`FloatingOriginSettings::precise_translation`].
+///
+/// The position is relative to the center of the grid the entity is in: the root grid, or the
+/// [reference frame](crate::reference_frame) of its parent. The rotation and scale of the entity's
+/// [`Transform`] are kept. If the position is past the edge of the grid, the entity is placed in
+/// the outermost cell.
+///
+/// If the entity has a [`FixedTranslation`], it is updated as well.
+pub struct SetGridPosition<P: GridPrecision> {
+    pub entity: Entity,
+    pub position: DVec3,
+    phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision> SetGridPosition<P> {
+    pub fn new(entity: Entity, position: DVec3) -> Self {
+        Self {
+            entity,
+            position,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P: GridPrecision> Command for SetGridPosition<P> {
+    fn write(self, world: &mut World) {
+        let settings = world.resource::<FloatingOriginSettings>();
+        let (cell, translation) = settings
+            .checked_precise_translation::<P>(self.position)
+            .unwrap_or_else(|| {
+                warn!(
+                    "Unable to place {:?} at {}, it is past the edge of the grid",
+                    self.entity, self.position
+                );
+                settings.precise_translation(self.position)
+            });
+        let fixed =
+            FixedTranslation::from_dvec3(self.position - settings.grid_position_double(&cell));
+
+        if let Some(mut entity) = world.get_entity_mut(self.entity) {
+            if entity.contains::<FixedTranslation>() {
+                entity.insert(fixed);
+            }
+            let transform = entity
+                .get::<Transform>()
+                .copied()
+                .unwrap_or_default()
+                .with_translation(translation);
+            entity.insert((cell, transform));
+        }
+    }
+}
+
+/// Extends [`Commands`] with methods for spawning grid entities.
+pub trait GridCommandsExt<'w, 's> {
+    /// Spawns `bundle` at the absolute `position`. See [`SetGridPosition`].
+    fn spawn_at<P: GridPrecision>(
+        &mut self,
+        position: DVec3,
+        bundle: impl Bundle,
+    ) -> EntityCommands<'w, 's, '_>;
+}
+
+impl<'w, 's> GridCommandsExt<'w, 's> for Commands<'w, 's> {
+    fn spawn_at<P: GridPrecision>(
+        &mut self,
+        position: DVec3,
+        bundle: impl Bundle,
+    ) -> EntityCommands<'w, 's, '_> {
+        let mut entity = self.spawn(bundle);
+        entity.set_grid_position::<P>(position);
+        entity
+    }
+}
+
+/// Extends [`EntityCommands`] with methods for positioning grid entities.
+pub trait GridEntityCommandsExt {
+    /// Teleports the entity to the absolute `position`. See [`SetGridPosition`].
+    fn set_grid_position<P: GridPrecision>(&mut self, position: DVec3) -> &mut Self;
+}
+
+impl<'w, 's, 'a> GridEntityCommandsExt for EntityCommands<'w, 's, 'a> {
+    fn set_grid_position<P: GridPrecision>(&mut self, position: DVec3) -> &mut Self {
+        let entity = self.id();
+        self.commands()
+            .add(SetGridPosition::<P>::new(entity, position));
+        self
+    }
+}
+
+/// Moves the [`FloatingOrigin`] from one entity to another, and immediately recomputes every
+/// [`GlobalTransform`] relative to the new origin.
+///
+/// Because the transforms are recomputed when the command is applied, the handoff is seamless no
+/// matter where in the frame it is issued: there is never a frame without an origin, or with stale
+/// global transforms. See [`update_global_transforms`].
+///
+/// The exception is a [`FarField`](crate::far_field::FarField) in the [`FloatingOriginSettings`]:
+/// only the entities near the new origin are recomputed at once. The far ones are queued, and keep
+/// stale global transforms for the next few frames, like after any other move of the origin.
+///
+/// Nothing happens if `from` is not a [`FloatingOrigin`], or `to` is not a grid entity.
+pub struct MoveFloatingOrigin<P: GridPrecision> {
+    /// The entity that is currently the floating origin.
+    pub from: Entity,
+    /// The entity that should become the floating origin. This must be a grid entity.
+    pub to: Entity,
+    phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision> MoveFloatingOrigin<P> {
+    pub fn new(from: Entity, to: Entity) -> Self {
+        Self {
+            from,
+            to,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P: GridPrecision> Command for MoveFloatingOrigin<P> {
+    fn write(self, world: &mut World) {
+        if world.get::<FloatingOrigin>(self.from).is_none() {
+            warn!(
+                "Unable to move the floating origin from {:?}, it is not a floating origin",
+                self.from
+            );
+            return;
+        }
+        if world.get::<GridCell<P>>(self.to).is_none() {
+            warn!(
+                "Unable to move the floating origin to {:?}, it is not a grid entity",
+                self.to
+            );
+            return;
+        }
+        world.entity_mut(self.from).remove::<FloatingOrigin>();
+        world.entity_mut(self.to).insert(FloatingOrigin);
+        update_global_transforms::<P>(world);
+    }
+}
+
+/// Extends [`Commands`] with methods for working with the floating origin.
+pub trait FloatingOriginCommandsExt {
+    /// Moves the [`FloatingOrigin`] from `from` to `to`. See [`MoveFloatingOrigin`].
+    fn move_floating_origin<P: GridPrecision>(&mut self, from: Entity, to: Entity);
+}
+
+impl<'w, 's> FloatingOriginCommandsExt for Commands<'w, 's> {
+    fn move_floating_origin<P: GridPrecision>(&mut self, from: Entity, to: Entity) {
+        self.add(MoveFloatingOrigin::<P>::new(from, to));
+    }
+}
+
+/// Runs every system that computes [`GlobalTransform`]s from the grid, once, outside of the
+/// schedule.
+///
+/// The systems are kept between calls, so like in the schedule, only entities that moved since the
+/// last call, and entities using an origin that moved, are updated. Every entity is updated the
+/// first time this is called. With a [`FarField`](crate::far_field::FarField), far entities are
+/// only queued, and are updated by the schedule over the next frames.
+///
+/// Entities are not recentered on the grid, that is still left to
+/// [`recenter_transform_on_grid`](crate::recenter_transform_on_grid), so its events are only sent
+/// once.
+pub fn update_global_transforms<P: GridPrecision>(world: &mut World) {
+    let mut systems = match world.remove_resource::<GlobalTransformSystems<P>>() {
+        Some(systems) => systems,
+        None => GlobalTransformSystems::new(world),
+    };
+    for system in &mut systems.systems {
+        system.run((), world);
+        system.apply_buffers(world);
+    }
+    world.insert_resource(systems);
+}
+
+/// The systems run by [`update_global_transforms`], in order.
+#[derive(Resource)]
+struct GlobalTransformSystems<P: GridPrecision> {
+    systems: Vec<BoxedSystem>,
+    phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision> GlobalTransformSystems<P> {
+    fn new(world: &mut World) -> Self {
+        let mut systems: Vec<BoxedSystem> = vec![
+            Box::new(IntoSystem::into_system(update_reference_frames::<P>)),
+            Box::new(IntoSystem::into_system(update_global_from_grid::<P>)),
+            Box::new(IntoSystem::into_system(transform_propagate_system::<P>)),
+        ];
+        for system in &mut systems {
+            system.initialize(world);
+        }
+        Self {
+            systems,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FloatingOriginPlugin;
+
+    #[test]
+    fn move_floating_origin() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let a = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                FloatingOrigin,
+            ))
+            .id();
+        let b = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::new(1, 0, 0)))
+            .id();
+        let x = |world: &World, entity| {
+            world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+                .x
+        };
+        app.update();
+        assert_eq!(x(&app.world, b), 10_000.0);
+
+        // Applied without running the schedule.
+        MoveFloatingOrigin::<i64>::new(a, b).write(&mut app.world);
+        assert!(app.world.get::<FloatingOrigin>(b).is_some());
+        assert_eq!(x(&app.world, a), -10_000.0);
+        assert_eq!(x(&app.world, b), 0.0);
+
+        // `a` is not the origin anymore.
+        let c = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::ZERO))
+            .id();
+        MoveFloatingOrigin::<i64>::new(a, c).write(&mut app.world);
+        assert!(app.world.get::<FloatingOrigin>(b).is_some());
+        assert!(app.world.get::<FloatingOrigin>(c).is_none());
+
+        MoveFloatingOrigin::<i64>::new(b, a).write(&mut app.world);
+        assert_eq!(x(&app.world, a), 0.0);
+        assert_eq!(x(&app.world, b), 10_000.0);
+        app.update();
+        assert_eq!(x(&app.world, b), 10_000.0);
+    }
+}
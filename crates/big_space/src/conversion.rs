@@ -0,0 +1,259 @@
+//! Converting grid positions between precisions and grid settings.
+//!
+//! Worlds saved with one [`GridPrecision`] or [`FloatingOriginSettings`] can be migrated to another
+//! with [`convert_world`]. Positions are converted exactly whenever one grid edge length is a whole
+//! multiple of the other, e.g. from 10 km cells to 1 km cells, or back.
+
+use std::fmt;
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{
+    fixed_point::FixedTranslation,
+    precision::{GridPrecision, I256},
+    FloatingOriginSettings, GridCell,
+};
+
+/// A position could not be converted, because it is past the edge of the new grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridConversionError {
+    /// The entity whose position could not be converted, if converting a world.
+    pub entity: Option<Entity>,
+}
+
+impl fmt::Display for GridConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.entity {
+            Some(entity) => write!(f, "{entity:?} is past the edge of the new grid"),
+            None => write!(f, "the position is past the edge of the new grid"),
+        }
+    }
+}
+
+impl std::error::Error for GridConversionError {}
+
+const OVERFLOW: GridConversionError = GridConversionError { entity: None };
+
+/// Converts a cell to another precision, without changing its position.
+pub fn convert_cell<F: GridPrecision, T: GridPrecision>(
+    cell: &GridCell<F>,
+) -> Result<GridCell<T>, GridConversionError> {
+    Ok(GridCell {
+        x: T::checked_from_i256(cell.x.as_i256()).ok_or(OVERFLOW)?,
+        y: T::checked_from_i256(cell.y.as_i256()).ok_or(OVERFLOW)?,
+        z: T::checked_from_i256(cell.z.as_i256()).ok_or(OVERFLOW)?,
+    })
+}
+
+/// Converts the position (`cell`, `translation`) in a grid using the `from` settings to the same
+/// position in a grid using the `to` settings, recentered on the new grid.
+///
+/// The conversion is exact if one grid edge length is a whole multiple of the other, apart from
+/// rounding the translation within the new cell. Otherwise, the position is converted with `f64`
+/// math, which is only exact for cells within 2^53 cell lengths of the center of the grid.
+pub fn convert_position<F: GridPrecision, T: GridPrecision>(
+    from: &FloatingOriginSettings,
+    to: &FloatingOriginSettings,
+    cell: &GridCell<F>,
+    translation: DVec3,
+) -> Result<(GridCell<T>, DVec3), GridConversionError> {
+    let from_edge = from.grid_edge_length();
+    let to_edge = to.grid_edge_length();
+    let axes = [cell.x, cell.y, cell.z].map(GridPrecision::as_i256);
+    let mut translation = translation.to_array();
+
+    let mut cells = [I256::ZERO; 3];
+    if let Some(ratio) = whole_ratio(from_edge, to_edge) {
+        // Every old cell is exactly `ratio` new cells.
+        for (cell, axis) in cells.iter_mut().zip(axes) {
+            *cell = axis.checked_mul_u64(ratio).ok_or(OVERFLOW)?;
+        }
+    } else if let Some(ratio) = whole_ratio(to_edge, from_edge) {
+        // Every new cell is exactly `ratio` old cells. The old cells left over after dividing are
+        // moved into the translation.
+        for ((cell, axis), translation) in cells.iter_mut().zip(axes).zip(&mut translation) {
+            let (quotient, remainder) = axis.div_rem_u64(ratio);
+            *cell = quotient;
+            *translation += remainder as f64 * from_edge;
+        }
+    } else {
+        let position = from.grid_position_double(cell) + DVec3::from(translation);
+        let nearest = (position / to_edge).round();
+        for (cell, nearest) in cells.iter_mut().zip(nearest.to_array()) {
+            *cell = I256::checked_from_f64(nearest).ok_or(OVERFLOW)?;
+        }
+        translation = (position - nearest * to_edge).to_array();
+    }
+
+    // Move into the nearest cell of the new grid.
+    for (cell, translation) in cells.iter_mut().zip(&mut translation) {
+        if translation.abs() > to.maximum_distance_from_origin() {
+            let delta = (*translation / to_edge).round();
+            *cell = cell.checked_add_f64(delta).ok_or(OVERFLOW)?;
+            *translation -= delta * to_edge;
+        }
+    }
+
+    let [x, y, z] = cells;
+    Ok((
+        convert_cell(&GridCell::new(x, y, z))?,
+        DVec3::from(translation),
+    ))
+}
+
+/// Converts every grid entity in `world` from `GridCell<F>` to `GridCell<T>`, and from the
+/// [`FloatingOriginSettings`] in the world to the `to` settings, which then replace them. See
+/// [`convert_position`].
+///
+/// The translation of each entity is taken from its [`FixedTranslation`] if it has one, and its
+/// [`Transform`] otherwise; both are updated. If any entity can't be converted, the world is left
+/// unchanged, and the error names the entity.
+///
+/// Grid entities in [reference frames](crate::reference_frame) are converted the same way, as every
+/// grid shares the same settings. The resources of the
+/// [`FloatingOriginPlugin`](crate::FloatingOriginPlugin) are generic over the precision, so this is
+/// meant to be run before the plugin for `T` updates, e.g. right after loading an old save.
+pub fn convert_world<F: GridPrecision, T: GridPrecision>(
+    world: &mut World,
+    to: FloatingOriginSettings,
+) -> Result<(), GridConversionError> {
+    let from = world.resource::<FloatingOriginSettings>().clone();
+
+    let mut converted = Vec::new();
+    let mut query = world.query::<(
+        Entity,
+        &GridCell<F>,
+        Option<&Transform>,
+        Option<&FixedTranslation>,
+    )>();
+    for (entity, cell, transform, fixed) in query.iter(world) {
+        let translation = match (fixed, transform) {
+            (Some(fixed), _) => fixed.as_dvec3(),
+            (None, Some(transform)) => transform.translation.as_dvec3(),
+            (None, None) => DVec3::ZERO,
+        };
+        let (cell, translation) =
+            convert_position::<F, T>(&from, &to, cell, translation).map_err(|_| {
+                GridConversionError {
+                    entity: Some(entity),
+                }
+            })?;
+        converted.push((entity, cell, translation));
+    }
+
+    for (entity, cell, translation) in converted {
+        let mut entity = world.entity_mut(entity);
+        if let Some(mut fixed) = entity.get_mut::<FixedTranslation>() {
+            *fixed = FixedTranslation::from_dvec3(translation);
+        }
+        if let Some(mut transform) = entity.get_mut::<Transform>() {
+            transform.translation = translation.as_vec3();
+        }
+        entity.remove::<GridCell<F>>();
+        entity.insert(cell);
+    }
+    world.insert_resource(to);
+    Ok(())
+}
+
+/// Returns `a / b` if it is a whole number that fits in a `u64`.
+fn whole_ratio(a: f64, b: f64) -> Option<u64> {
+    let ratio = (a / b).round();
+    (ratio >= 1.0 && ratio < u64::MAX as f64 && ratio * b == a).then_some(ratio as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refine() {
+        let from = FloatingOriginSettings::new(10_000.0, 100.0);
+        let to = FloatingOriginSettings::new(1_000.0, 10.0);
+        let cell = GridCell::<i64>::new(i64::MAX, i64::MIN, 0);
+        let (new_cell, translation) =
+            convert_position::<i64, i128>(&from, &to, &cell, DVec3::new(4_900.25, -0.5, 700.0))
+                .unwrap();
+        assert_eq!(
+            new_cell,
+            GridCell::new(i64::MAX as i128 * 10 + 5, i64::MIN as i128 * 10, 1)
+        );
+        assert_eq!(translation, DVec3::new(-99.75, -0.5, -300.0));
+    }
+
+    #[test]
+    fn coarsen() {
+        let from = FloatingOriginSettings::new(1_000.0, 10.0);
+        let to = FloatingOriginSettings::new(10_000.0, 100.0);
+        let cell = GridCell::<i128>::new(i128::MAX, -14, 16);
+        let (new_cell, translation) =
+            convert_position::<i128, I256>(&from, &to, &cell, DVec3::new(0.25, 0.0, 0.0)).unwrap();
+        assert_eq!(
+            new_cell,
+            GridCell::new(
+                I256::from(i128::MAX / 10 + 1),
+                I256::from(-1),
+                I256::from(2)
+            )
+        );
+        assert_eq!(translation, DVec3::new(-2_999.75, -4_000.0, -4_000.0));
+    }
+
+    #[test]
+    fn overflow() {
+        let settings = FloatingOriginSettings::default();
+        let cell = GridCell::<i16>::new(128, 0, 0);
+        assert_eq!(convert_cell::<i16, i8>(&cell), Err(OVERFLOW));
+        let cell = GridCell::<i16>::new(-1, 0, 0);
+        assert_eq!(
+            convert_position::<i16, u8>(&settings, &settings, &cell, DVec3::ZERO),
+            Err(OVERFLOW)
+        );
+        let finer = FloatingOriginSettings::new(1.0, 0.01);
+        let cell = GridCell::new(I256::MAX, I256::ZERO, I256::ZERO);
+        assert_eq!(
+            convert_position::<I256, I256>(&settings, &finer, &cell, DVec3::ZERO),
+            Err(OVERFLOW)
+        );
+    }
+
+    #[test]
+    fn world() {
+        let mut world = World::new();
+        world.insert_resource(FloatingOriginSettings::new(10_000.0, 100.0));
+        let entity = world
+            .spawn((
+                GridCell::<i8>::new(1, 2, 3),
+                Transform::from_xyz(0.0, 3_000.0, 0.0),
+            ))
+            .id();
+        let fixed = world
+            .spawn((GridCell::<i8>::ONE, FixedTranslation::new(1, 0, 0)))
+            .id();
+
+        convert_world::<i8, i64>(&mut world, FloatingOriginSettings::new(2_000.0, 10.0)).unwrap();
+        assert_eq!(
+            world
+                .resource::<FloatingOriginSettings>()
+                .grid_edge_length(),
+            2_000.0
+        );
+        assert!(world.get::<GridCell<i8>>(entity).is_none());
+        assert_eq!(
+            world.get::<GridCell<i64>>(entity),
+            Some(&GridCell::new(5, 12, 15))
+        );
+        assert_eq!(
+            world.get::<Transform>(entity).unwrap().translation,
+            Vec3::new(0.0, -1_000.0, 0.0)
+        );
+        assert_eq!(
+            world.get::<GridCell<i64>>(fixed),
+            Some(&GridCell::new(5, 5, 5))
+        );
+        assert_eq!(
+            world.get::<FixedTranslation>(fixed),
+            Some(&FixedTranslation::new(1, 0, 0))
+        );
+    }
+}
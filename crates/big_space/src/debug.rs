@@ -1,9 +1,12 @@
 use std::marker::PhantomData;
 
-use bevy::{prelude::*, utils::HashMap};
+use bevy::prelude::*;
 use bevy_polyline::prelude::*;
 
-use crate::{precision::GridPrecision, FloatingOrigin, FloatingOriginSettings, GridCell};
+use crate::{
+    grid_index::GridIndex, precision::GridPrecision, FloatingOrigin, FloatingOriginSettings,
+    FloatingOriginSystem, GridCell,
+};
 
 #[derive(Default)]
 pub struct FloatingOriginDebugPlugin<P: GridPrecision>(PhantomData<P>);
@@ -14,8 +17,8 @@ impl<P: GridPrecision> Plugin for FloatingOriginDebugPlugin<P> {
             .add_system_to_stage(
                 CoreStage::PostUpdate,
                 update_debug_bounds::<P>
-                    .after(crate::recenter_transform_on_grid::<P>)
-                    .before(crate::update_global_from_grid::<P>),
+                    .after(FloatingOriginSystem::UpdateGridIndex)
+                    .before(FloatingOriginSystem::GridToGlobal),
             );
     }
 }
@@ -33,7 +36,8 @@ pub struct CubePolyline {
 pub fn update_debug_bounds<P: GridPrecision>(
     mut commands: Commands,
     cube_polyline: Res<CubePolyline>,
-    occupied_cells: Query<(&GridCell<P>, Option<&FloatingOrigin>), Without<DebugBounds>>,
+    index: Res<GridIndex<P>>,
+    origin: Query<&GridCell<P>, (With<FloatingOrigin>, Without<DebugBounds>)>,
     mut debug_bounds: Query<
         (
             &mut GridCell<P>,
@@ -44,7 +48,15 @@ pub fn update_debug_bounds<P: GridPrecision>(
         With<DebugBounds>,
     >,
 ) {
-    let mut occupied_cells = HashMap::from_iter(occupied_cells.iter()).into_iter();
+    let origin_cells = origin.iter().copied().collect::<Vec<_>>();
+    // The debug bounds are grid entities themselves, so they must be ignored when finding occupied
+    // cells.
+    let mut occupied_cells = index
+        .occupied_cells()
+        .filter(|(_, entities)| entities.iter().any(|e| !debug_bounds.contains(*e)))
+        .map(|(cell, _)| (*cell, origin_cells.contains(cell)))
+        .collect::<Vec<_>>()
+        .into_iter();
 
     for (mut cell, mut polyline, mut matl, mut visibility) in &mut debug_bounds {
         if cube_polyline.is_changed() {
@@ -52,8 +64,8 @@ pub fn update_debug_bounds<P: GridPrecision>(
         }
         if let Some((occupied_cell, has_origin)) = occupied_cells.next() {
             visibility.is_visible = true;
-            *cell = *occupied_cell;
-            if has_origin.is_some() {
+            *cell = occupied_cell;
+            if has_origin {
                 *matl = cube_polyline.origin_matl.clone();
             } else {
                 *matl = cube_polyline.material.clone();
@@ -66,7 +78,7 @@ pub fn update_debug_bounds<P: GridPrecision>(
 
     // If there are still occupied cells but no more debug bounds, we need to spawn more.
     for (occupied_cell, has_origin) in occupied_cells {
-        let material = if has_origin.is_some() {
+        let material = if has_origin {
             cube_polyline.origin_matl.clone()
         } else {
             cube_polyline.material.clone()
@@ -75,7 +87,7 @@ pub fn update_debug_bounds<P: GridPrecision>(
             SpatialBundle::default(),
             cube_polyline.polyline.clone(),
             material,
-            occupied_cell.to_owned(),
+            occupied_cell,
             DebugBounds,
         ));
     }
@@ -91,7 +103,7 @@ pub fn build_cube(
         return;
     }
 
-    let s = settings.grid_edge_length / 2.001;
+    let s = (settings.grid_edge_length / 2.001) as f32;
 
     /*
         (2)-----(3)               Y
@@ -0,0 +1,31 @@
+//! Events sent when grid entities or the floating origin move between grid cells.
+
+use bevy::prelude::*;
+
+use crate::{precision::GridPrecision, GridCell};
+
+/// Sent by [`recenter_transform_on_grid`](crate::recenter_transform_on_grid) when an entity moves
+/// into a new [`GridCell`] because its [`Transform`] moved past the edge of its cell. Also sent when
+/// an entity is moved into a new cell by [`kinematics`](crate::kinematics), or by the
+/// interpolation of the [`fixed_timestep`](crate::fixed_timestep) module.
+///
+/// Unlike `Changed<GridCell<P>>`, this is not sent when the cell is edited directly.
+#[derive(Debug, Clone, Copy)]
+pub struct GridCellChanged<P: GridPrecision> {
+    pub entity: Entity,
+    pub old_cell: GridCell<P>,
+    pub new_cell: GridCell<P>,
+}
+
+/// Sent by [`update_reference_frames`](crate::reference_frame::update_reference_frames) when the
+/// cell of a [`FloatingOrigin`](crate::FloatingOrigin) changes, for any reason. Every
+/// [`GlobalTransform`] relative to that origin is shifted when this happens.
+///
+/// If the origin also moved into a different [reference frame](crate::reference_frame), the old
+/// and new cells are in different frames.
+#[derive(Debug, Clone, Copy)]
+pub struct FloatingOriginShifted<P: GridPrecision> {
+    pub origin: Entity,
+    pub old_cell: GridCell<P>,
+    pub new_cell: GridCell<P>,
+}
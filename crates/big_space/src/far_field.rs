@@ -0,0 +1,232 @@
+//! Spreading the cost of moving the floating origin across frames.
+//!
+//! When a [`FloatingOrigin`](crate::FloatingOrigin) moves to a new cell, the [`GlobalTransform`] of
+//! every grid entity using it has to be recomputed. In worlds with hundreds of thousands of grid
+//! entities, doing this all at once causes a frame spike, even though most of those entities are
+//! far away, and barely move on screen.
+//!
+//! With a [`FarField`] in the [`FloatingOriginSettings`](crate::FloatingOriginSettings), only
+//! entities in cells near the origin are updated immediately, found through the
+//! [`GridIndex`](crate::grid_index::GridIndex) without visiting the rest of the world. The other
+//! occupied cells are added to the [`FarFieldQueue`], and their entities updated a few cells at a
+//! time over the following frames. Entities inside a [reference frame](crate::reference_frame) are
+//! updated along with the entity that defines it. Entities that move, or whose frame moves, are
+//! always updated immediately.
+//!
+//! Far cells are only queued when the origin moves to another cell. When the origin moves within
+//! its cell, like a camera in [`OriginMode::CameraRelative`](crate::OriginMode::CameraRelative),
+//! only the entities near it are updated, and far entities keep their slightly stale transforms
+//! until the origin changes cells.
+
+use std::collections::VecDeque;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{precision::GridPrecision, GridCell};
+
+/// Configures which grid entities are updated lazily when the origin moves. See the
+/// [module docs](crate::far_field).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, FromReflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FarField {
+    /// Entities within this many cells of the origin along every axis are updated immediately.
+    pub cell_radius: u32,
+    /// The maximum number of far away entities updated each frame. Queued cells are updated whole,
+    /// so the entities of the last cell updated in a frame may go over this.
+    pub entities_per_update: usize,
+}
+
+impl FarField {
+    pub fn new(cell_radius: u32, entities_per_update: usize) -> Self {
+        Self {
+            cell_radius,
+            entities_per_update,
+        }
+    }
+
+    /// Returns `true` if the root grid `cell` is far enough from `origin_cell` to be updated
+    /// lazily.
+    pub fn is_far<P: GridPrecision>(&self, origin_cell: &GridCell<P>, cell: &GridCell<P>) -> bool {
+        let radius = self.cell_radius as f64;
+        cell.x.sub_f64(origin_cell.x).abs() > radius
+            || cell.y.sub_f64(origin_cell.y).abs() > radius
+            || cell.z.sub_f64(origin_cell.z).abs() > radius
+    }
+}
+
+/// Occupied cells of the root grid whose entities have stale [`GlobalTransform`]s, in the order
+/// they will be updated.
+#[derive(Resource)]
+pub struct FarFieldQueue<P: GridPrecision> {
+    queue: VecDeque<GridCell<P>>,
+    queued: HashSet<GridCell<P>>,
+    /// The root cell of each origin when the cells far from it were last queued.
+    origin_cells: HashMap<Entity, GridCell<P>>,
+}
+
+impl<P: GridPrecision> Default for FarFieldQueue<P> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            queued: HashSet::default(),
+            origin_cells: HashMap::default(),
+        }
+    }
+}
+
+impl<P: GridPrecision> FarFieldQueue<P> {
+    /// The number of cells waiting to be updated.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if every [`GlobalTransform`] is up to date.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Returns `true` if the [`GlobalTransform`]s of the entities in `cell` are waiting to be
+    /// updated.
+    pub fn contains(&self, cell: &GridCell<P>) -> bool {
+        self.queued.contains(cell)
+    }
+
+    /// Adds cells to the back of the queue, unless they are already queued.
+    pub(crate) fn extend(&mut self, cells: impl IntoIterator<Item = GridCell<P>>) {
+        for cell in cells {
+            if self.queued.insert(cell) {
+                self.queue.push_back(cell);
+            }
+        }
+    }
+
+    /// Records that the cells far from `origin` are queued with the origin in the root grid `cell`.
+    /// Returns `false` if they already were queued from that cell.
+    pub(crate) fn queue_origin(&mut self, origin: Entity, cell: GridCell<P>) -> bool {
+        self.origin_cells.insert(origin, cell) != Some(cell)
+    }
+
+    /// Forgets where every origin was when its far cells were queued, so they are queued again.
+    pub(crate) fn clear_origins(&mut self) {
+        self.origin_cells.clear();
+    }
+
+    /// Removes the cell at the front of the queue.
+    pub(crate) fn pop(&mut self) -> Option<GridCell<P>> {
+        let cell = self.queue.pop_front()?;
+        self.queued.remove(&cell);
+        Some(cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FloatingOrigin, FloatingOriginPlugin, FloatingOriginSettings, GridCell, OriginMode,
+    };
+
+    #[test]
+    fn far_entities_are_updated_lazily() {
+        let settings =
+            FloatingOriginSettings::new(10_000.0, 100.0).with_far_field(FarField::new(2, 1));
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64> {
+            settings,
+            ..Default::default()
+        });
+        let spawn = |app: &mut App, cell| {
+            app.world
+                .spawn((TransformBundle::default(), GridCell::<i64>::new(cell, 0, 0)))
+                .id()
+        };
+        let origin = spawn(&mut app, 0);
+        app.world.entity_mut(origin).insert(FloatingOrigin);
+        let near = spawn(&mut app, 1);
+        let far = [spawn(&mut app, 10), spawn(&mut app, 20)];
+        // A grid entity inside the reference frame of a far entity, and a child outside the grid.
+        let inside = spawn(&mut app, 0);
+        let part = app.world.spawn(TransformBundle::default()).id();
+        app.world.entity_mut(far[1]).push_children(&[inside, part]);
+        app.update();
+
+        app.world.get_mut::<GridCell<i64>>(origin).unwrap().x = -1;
+        app.update();
+        let x = |app: &App, entity| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+                .x
+        };
+        assert_eq!(x(&app, near), 20_000.0);
+        assert_eq!(app.world.resource::<FarFieldQueue<i64>>().len(), 1);
+        assert_eq!(x(&app, far[0]), 110_000.0);
+        assert_eq!(x(&app, far[1]), 200_000.0);
+        assert_eq!(x(&app, inside), 200_000.0);
+        assert_eq!(x(&app, part), 200_000.0);
+
+        app.update();
+        assert!(app.world.resource::<FarFieldQueue<i64>>().is_empty());
+        assert_eq!(x(&app, far[1]), 210_000.0);
+        assert_eq!(x(&app, inside), 210_000.0);
+        assert_eq!(x(&app, part), 210_000.0);
+    }
+
+    /// In camera-relative mode, far cells are only queued again when the camera changes cells.
+    #[test]
+    fn camera_relative_queues_on_cell_change() {
+        let settings = FloatingOriginSettings::new(10_000.0, 100.0)
+            .with_origin_mode(OriginMode::CameraRelative)
+            .with_far_field(FarField::new(2, 1));
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64> {
+            settings,
+            ..Default::default()
+        });
+        let origin = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                FloatingOrigin,
+            ))
+            .id();
+        let near = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::new(1, 0, 0)))
+            .id();
+        for x in [10, 20] {
+            app.world
+                .spawn((TransformBundle::default(), GridCell::<i64>::new(x, 0, 0)));
+        }
+        let queued = |app: &App| app.world.resource::<FarFieldQueue<i64>>().len();
+        app.update();
+        app.update();
+        assert_eq!(queued(&app), 0);
+
+        for _ in 0..3 {
+            app.world
+                .get_mut::<Transform>(origin)
+                .unwrap()
+                .translation
+                .x += 1.0;
+            app.update();
+            assert_eq!(queued(&app), 0);
+        }
+        let near_x = app
+            .world
+            .get::<GlobalTransform>(near)
+            .unwrap()
+            .translation()
+            .x;
+        assert_eq!(near_x, 9_997.0);
+
+        app.world.get_mut::<GridCell<i64>>(origin).unwrap().x = 1;
+        app.update();
+        assert_eq!(queued(&app), 1);
+    }
+}
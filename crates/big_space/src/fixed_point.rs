@@ -0,0 +1,180 @@
+//! Fixed-point positions within a grid cell.
+//!
+//! The translation of a [`Transform`] is an `f32`, and recentering it on the grid with `f64` math
+//! can round differently depending on the order operations are done in, or the platform. That is
+//! fine for rendering, but not for lockstep simulations or replays, where every machine must
+//! compute exactly the same positions.
+//!
+//! Adding a [`FixedTranslation`] to a grid entity makes it the source of truth for the entity's
+//! position within its [`GridCell`]. Recentering is done with integer math, and the translation of
+//! the entity's [`Transform`] is overwritten with the fixed-point value every time either changes.
+//! Rotation and scale are still taken from the [`Transform`].
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{precision::GridPrecision, FloatingOriginSettings, GridCell};
+
+/// A fixed-point translation of a grid entity, relative to the center of its [`GridCell`]. See the
+/// [module docs](crate::fixed_point).
+///
+/// Each axis counts in steps of 2^-[`FRACTIONAL_BITS`](Self::FRACTIONAL_BITS) units of length, or
+/// about 0.23 nanometers if the grid is in meters, with a range of about ±2.1e9 units. Move the
+/// entity by changing this component; changes to the translation of its [`Transform`] are
+/// discarded.
+#[derive(Component, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Reflect)]
+#[reflect(Component, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FixedTranslation {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl FixedTranslation {
+    /// The number of bits of each axis used for the fractional part.
+    pub const FRACTIONAL_BITS: u32 = 32;
+
+    /// The value of one unit of length.
+    pub const ONE_UNIT: i64 = 1 << Self::FRACTIONAL_BITS;
+
+    pub const ZERO: Self = Self::new(0, 0, 0);
+
+    pub const fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Converts from a translation in units of length, rounding to the nearest step, and saturating
+    /// at the bounds of the range.
+    pub fn from_dvec3(translation: DVec3) -> Self {
+        let scaled = (translation * Self::ONE_UNIT as f64).round();
+        Self::new(scaled.x as i64, scaled.y as i64, scaled.z as i64)
+    }
+
+    /// Converts to a translation in units of length.
+    pub fn as_dvec3(&self) -> DVec3 {
+        DVec3::new(self.x as f64, self.y as f64, self.z as f64) / Self::ONE_UNIT as f64
+    }
+
+    /// Converts to a translation in units of length, rounded to `f32`.
+    pub fn as_vec3(&self) -> Vec3 {
+        self.as_dvec3().as_vec3()
+    }
+
+    /// Like [`FloatingOriginSettings::checked_recenter`], but computed exactly with integer math.
+    pub fn checked_recenter<P: GridPrecision>(
+        &self,
+        settings: &FloatingOriginSettings,
+        cell: &GridCell<P>,
+    ) -> Option<(GridCell<P>, Self)> {
+        let (delta, translation) = self.recenter_delta(settings);
+        Some((cell.checked_add_f64(delta)?, translation))
+    }
+
+    /// Like [`FloatingOriginSettings::recenter`], but computed exactly with integer math.
+    pub fn recenter<P: GridPrecision>(
+        &self,
+        settings: &FloatingOriginSettings,
+        cell: &GridCell<P>,
+    ) -> (GridCell<P>, Self) {
+        let (delta, translation) = self.recenter_delta(settings);
+        (cell.saturating_add_f64(delta), translation)
+    }
+
+    /// Returns the number of cells to move along each axis, and the translation within the new
+    /// cell.
+    fn recenter_delta(&self, settings: &FloatingOriginSettings) -> (DVec3, Self) {
+        // Multiplying by a power of two is exact, so these are the same on every platform.
+        let edge_length = (settings.grid_edge_length() * Self::ONE_UNIT as f64).round() as i128;
+        let maximum_distance =
+            (settings.maximum_distance_from_origin() * Self::ONE_UNIT as f64).round() as i128;
+
+        let axes = [self.x, self.y, self.z].map(i128::from);
+        if axes.iter().all(|axis| axis.abs() <= maximum_distance) {
+            return (DVec3::ZERO, *self);
+        }
+        // Round to the nearest cell, with ties away from zero like `f64::round`.
+        let [dx, dy, dz] =
+            axes.map(|axis| (2 * axis + axis.signum() * edge_length) / (2 * edge_length));
+        let remainder = |axis: i128, delta: i128| (axis - delta * edge_length) as i64;
+
+        // The range of a fixed translation is much smaller than the range of `f64` integers, so
+        // the number of cells can be converted exactly.
+        (
+            DVec3::new(dx as f64, dy as f64, dz as f64),
+            Self::new(
+                remainder(axes[0], dx),
+                remainder(axes[1], dy),
+                remainder(axes[2], dz),
+            ),
+        )
+    }
+}
+
+impl std::ops::Add for FixedTranslation {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl std::ops::Sub for FixedTranslation {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl std::ops::AddAssign for FixedTranslation {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl std::ops::SubAssign for FixedTranslation {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recenter_is_exact() {
+        let settings = FloatingOriginSettings::new(10_000.0, 100.0);
+        let step = 1;
+        let edge = 10_000 * FixedTranslation::ONE_UNIT;
+        let cell = GridCell::<i64>::new(5, -5, 0);
+
+        // Within the switching threshold, nothing moves.
+        let inside = FixedTranslation::new(edge / 2 + step, 0, -edge / 2);
+        assert_eq!(
+            inside.checked_recenter(&settings, &cell),
+            Some((cell, inside))
+        );
+
+        let outside = FixedTranslation::new(3 * edge + step, -edge / 2 - edge / 10, 0);
+        let (new_cell, translation) = outside.checked_recenter(&settings, &cell).unwrap();
+        assert_eq!(new_cell, GridCell::new(8, -6, 0));
+        assert_eq!(
+            translation,
+            FixedTranslation::new(step, edge / 2 - edge / 10, 0)
+        );
+    }
+
+    #[test]
+    fn recenter_overflow() {
+        let settings = FloatingOriginSettings::default();
+        let edge = 10_000 * FixedTranslation::ONE_UNIT;
+        let cell = GridCell::<u8>::new(0, u8::MAX, 0);
+        let translation = FixedTranslation::new(-edge, edge, 0);
+        assert_eq!(translation.checked_recenter(&settings, &cell), None);
+        assert_eq!(
+            translation.recenter(&settings, &cell),
+            (cell, FixedTranslation::ZERO)
+        );
+    }
+}
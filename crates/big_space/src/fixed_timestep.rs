@@ -0,0 +1,306 @@
+//! Simulating grid entities at a fixed timestep, and interpolating them for rendering.
+//!
+//! Systems added to the [`FixedUpdateStage`] run zero or more times each frame, once for every
+//! timestep that passed, so the simulation does not depend on the frame rate. Entities moved by the
+//! simulation would then stutter on screen, as the number of steps each frame varies, so
+//! [`Interpolated`] entities are rendered between their positions after the last two steps.
+//!
+//! Each frame:
+//!
+//! 1. In [`CoreStage::PreUpdate`], the [`GridCell`] and [`Transform`] of every [`Interpolated`]
+//!    entity are restored to their state after the last step, so systems in [`CoreStage::Update`]
+//!    and the [`FixedUpdateStage`] only ever see simulated positions.
+//! 2. The [`FixedUpdateStage`] runs once for every step, after [`CoreStage::Update`]. Entities with
+//!    a [`GridVelocity`](crate::kinematics::GridVelocity) are moved by each step.
+//! 3. In [`CoreStage::PostUpdate`], before
+//!    [`FloatingOriginSystem::RecenterGrid`](crate::FloatingOriginSystem::RecenterGrid), the
+//!    simulated state is saved, and replaced with the interpolated one for rendering.
+//!
+//! Positions are interpolated with `f64` math across cell boundaries. Entities with a
+//! [`FixedTranslation`](crate::fixed_point::FixedTranslation) are not interpolated.
+
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::system::SystemState,
+    prelude::*,
+    time::{FixedTimestep, FixedTimesteps},
+};
+
+use crate::{
+    events::GridCellChanged,
+    fixed_point::FixedTranslation,
+    kinematics::{integrate, Motion},
+    precision::GridPrecision,
+    FloatingOriginSettings, FloatingOriginSystem, GridCell,
+};
+
+/// The label of the [`FixedTimestep`] of the [`FixedUpdateStage`], used to look up its state in
+/// [`FixedTimesteps`].
+pub const FIXED_TIMESTEP_LABEL: &str = "big_space_fixed_timestep";
+
+/// The stage simulation systems are added to. See the [module docs](crate::fixed_timestep).
+#[derive(StageLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FixedUpdateStage;
+
+/// Runs the [`FixedUpdateStage`] at a fixed timestep, and interpolates [`Interpolated`] entities
+/// between steps. See the [module docs](crate::fixed_timestep).
+pub struct FixedTimestepPlugin<P: GridPrecision> {
+    /// The length of each step, in seconds.
+    pub timestep: f64,
+    pub phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision> Default for FixedTimestepPlugin<P> {
+    fn default() -> Self {
+        Self {
+            timestep: 1.0 / 60.0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P: GridPrecision> Plugin for FixedTimestepPlugin<P> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FixedTimesteps>()
+            .add_stage_after(
+                CoreStage::Update,
+                FixedUpdateStage,
+                SystemStage::parallel().with_run_criteria(
+                    FixedTimestep::step(self.timestep).with_label(FIXED_TIMESTEP_LABEL),
+                ),
+            )
+            .add_system_to_stage(CoreStage::PreUpdate, restore_simulated_state::<P>)
+            .add_system_to_stage(FixedUpdateStage, begin_step::<P>.at_start())
+            .add_system_to_stage(
+                FixedUpdateStage,
+                integrate_fixed_motion::<P>.label(FloatingOriginSystem::Integrate),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_transforms::<P>.before(FloatingOriginSystem::RecenterGrid),
+            );
+    }
+}
+
+/// Renders a grid entity between its simulated positions after the last two steps of the
+/// [`FixedUpdateStage`]. See the [module docs](crate::fixed_timestep).
+#[derive(Component)]
+pub struct Interpolated<P: GridPrecision> {
+    previous: Option<Snapshot<P>>,
+    current: Option<Snapshot<P>>,
+}
+
+impl<P: GridPrecision> Default for Interpolated<P> {
+    fn default() -> Self {
+        Self {
+            previous: None,
+            current: None,
+        }
+    }
+}
+
+/// The simulated position of an entity at one point in time.
+#[derive(Debug, Clone, Copy)]
+struct Snapshot<P: GridPrecision> {
+    cell: GridCell<P>,
+    transform: Transform,
+}
+
+impl<P: GridPrecision> Snapshot<P> {
+    /// Returns the position `alpha` of the way from `self` to `next`, recentered on the grid.
+    fn lerp(&self, settings: &FloatingOriginSettings, next: &Self, alpha: f64) -> Self {
+        let offset = settings.grid_offset_double(&self.cell, &next.cell)
+            + next.transform.translation.as_dvec3()
+            - self.transform.translation.as_dvec3();
+        let translation = self.transform.translation.as_dvec3() + offset * alpha;
+        match settings.checked_recenter(&self.cell, translation) {
+            Some((cell, translation)) => Self {
+                cell,
+                transform: Transform {
+                    translation,
+                    rotation: self
+                        .transform
+                        .rotation
+                        .slerp(next.transform.rotation, alpha as f32),
+                    scale: self
+                        .transform
+                        .scale
+                        .lerp(next.transform.scale, alpha as f32),
+                },
+            },
+            None => *next,
+        }
+    }
+}
+
+/// Puts every [`Interpolated`] entity back where the simulation left it, sending a
+/// [`GridCellChanged`] event if that moves it into a different cell.
+pub fn restore_simulated_state<P: GridPrecision>(
+    mut entities: Query<
+        (Entity, &mut GridCell<P>, &mut Transform, &Interpolated<P>),
+        Without<FixedTranslation>,
+    >,
+    mut cell_events: EventWriter<GridCellChanged<P>>,
+) {
+    for (entity, cell, mut transform, interpolated) in &mut entities {
+        if let Some(current) = interpolated.current {
+            set_cell(entity, cell, current.cell, &mut cell_events);
+            *transform = current.transform;
+        }
+    }
+}
+
+/// Moves `entity` into `new_cell`, sending a [`GridCellChanged`] event if it is a different cell.
+fn set_cell<P: GridPrecision>(
+    entity: Entity,
+    mut cell: Mut<GridCell<P>>,
+    new_cell: GridCell<P>,
+    cell_events: &mut EventWriter<GridCellChanged<P>>,
+) {
+    if *cell != new_cell {
+        cell_events.send(GridCellChanged {
+            entity,
+            old_cell: *cell,
+            new_cell,
+        });
+        *cell = new_cell;
+    }
+}
+
+/// Saves the position of every [`Interpolated`] entity before each step. This runs at the start
+/// of the [`FixedUpdateStage`], before any other system in it.
+pub fn begin_step<P: GridPrecision>(
+    world: &mut World,
+    state: &mut SystemState<
+        Query<(&GridCell<P>, &Transform, &mut Interpolated<P>), Without<FixedTranslation>>,
+    >,
+) {
+    for (cell, transform, mut interpolated) in &mut state.get_mut(world) {
+        interpolated.previous = Some(Snapshot {
+            cell: *cell,
+            transform: *transform,
+        });
+    }
+}
+
+/// Like [`integrate_motion`](crate::kinematics::integrate_motion), but moves [`Interpolated`]
+/// entities by one step of the [`FixedUpdateStage`].
+pub fn integrate_fixed_motion<P: GridPrecision>(
+    settings: Res<FloatingOriginSettings>,
+    timesteps: Res<FixedTimesteps>,
+    mut entities: Query<Motion<P>, With<Interpolated<P>>>,
+    mut cell_events: EventWriter<GridCellChanged<P>>,
+) {
+    if let Some(timestep) = timesteps.get(FIXED_TIMESTEP_LABEL) {
+        integrate(&settings, timestep.step(), &mut entities, &mut cell_events);
+    }
+}
+
+/// Saves the simulated position of every [`Interpolated`] entity, and moves it to where it should
+/// be rendered, between the last two steps, sending a [`GridCellChanged`] event if that moves it
+/// into a different cell.
+pub fn interpolate_transforms<P: GridPrecision>(
+    settings: Res<FloatingOriginSettings>,
+    timesteps: Res<FixedTimesteps>,
+    mut entities: Query<
+        (
+            Entity,
+            &mut GridCell<P>,
+            &mut Transform,
+            &mut Interpolated<P>,
+        ),
+        Without<FixedTranslation>,
+    >,
+    mut cell_events: EventWriter<GridCellChanged<P>>,
+) {
+    let alpha = timesteps
+        .get(FIXED_TIMESTEP_LABEL)
+        .map_or(1.0, |timestep| timestep.overstep_percentage())
+        .clamp(0.0, 1.0);
+
+    for (entity, cell, mut transform, mut interpolated) in &mut entities {
+        // Keep the simulated translation within its cell, as it is only recentered here.
+        let (current_cell, translation) = settings
+            .checked_recenter(&cell, transform.translation.as_dvec3())
+            .unwrap_or((*cell, transform.translation));
+        let current = Snapshot {
+            cell: current_cell,
+            transform: Transform {
+                translation,
+                ..*transform
+            },
+        };
+        interpolated.current = Some(current);
+
+        let rendered = match interpolated.previous {
+            Some(previous) => previous.lerp(&settings, &current, alpha),
+            None => current,
+        };
+        set_cell(entity, cell, rendered.cell, &mut cell_events);
+        *transform = rendered.transform;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::event::ManualEventReader, math::DVec3};
+
+    use super::*;
+    use crate::{kinematics::GridVelocity, tests::step_app, FloatingOriginPlugin};
+
+    #[test]
+    fn interpolate_across_cells() {
+        let settings = FloatingOriginSettings::new(1.0, 0.0);
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64> {
+            settings: settings.clone(),
+            ..default()
+        })
+        .add_plugin(FixedTimestepPlugin::<i64> {
+            timestep: 0.1,
+            ..default()
+        });
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                GridVelocity(DVec3::new(10.0, 0.0, 0.0)),
+                Interpolated::<i64>::default(),
+            ))
+            .id();
+        let position = |app: &App| {
+            let cell = app.world.get::<GridCell<i64>>(entity).unwrap();
+            let transform = app.world.get::<Transform>(entity).unwrap();
+            settings.grid_position_double(cell) + transform.translation.as_dvec3()
+        };
+
+        // Every move between cells, whether simulated, restored or interpolated, is reported.
+        let mut reader = ManualEventReader::<GridCellChanged<i64>>::default();
+        let mut reported = GridCell::<i64>::ZERO;
+        let mut check_events = |app: &App| {
+            for event in reader.iter(app.world.resource()) {
+                assert_eq!(event.entity, entity);
+                assert_eq!(event.old_cell, reported);
+                reported = event.new_cell;
+            }
+            assert_eq!(app.world.get::<GridCell<i64>>(entity), Some(&reported));
+        };
+
+        step_app(&mut app, 0.0);
+        assert_eq!(position(&app), DVec3::ZERO);
+        check_events(&app);
+
+        // Two steps, each moving a whole cell, and half of the next step left over.
+        step_app(&mut app, 0.25);
+        assert!((position(&app) - DVec3::new(1.5, 0.0, 0.0)).length() < 1e-6);
+        check_events(&app);
+
+        // Simulated positions are restored before the next frame's steps.
+        step_app(&mut app, 0.1);
+        assert!((position(&app) - DVec3::new(2.5, 0.0, 0.0)).length() < 1e-6);
+        check_events(&app);
+        assert_ne!(reported, GridCell::ZERO);
+    }
+}
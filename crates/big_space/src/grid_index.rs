@@ -0,0 +1,255 @@
+//! A spatial index of occupied grid cells.
+
+use bevy::{
+    ecs::system::BoxedSystem,
+    math::DVec3,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{precision::GridPrecision, GridCell};
+
+/// Maps every occupied [`GridCell`] in the root grid to the entities inside of it.
+///
+/// The index is updated incrementally by [`update_grid_index`], only for entities whose cell
+/// changed, so it is cheap to keep around even for very large worlds. Entities inside nested
+/// [reference frames](crate::reference_frame) are not indexed, they can be found through the
+/// entity that defines their frame.
+#[derive(Resource)]
+pub struct GridIndex<P: GridPrecision> {
+    cells: HashMap<GridCell<P>, HashSet<Entity>>,
+    entities: HashMap<Entity, GridCell<P>>,
+}
+
+impl<P: GridPrecision> Default for GridIndex<P> {
+    fn default() -> Self {
+        Self {
+            cells: HashMap::default(),
+            entities: HashMap::default(),
+        }
+    }
+}
+
+impl<P: GridPrecision> GridIndex<P> {
+    /// Returns the cell `entity` is in, if it is indexed.
+    pub fn cell_of(&self, entity: Entity) -> Option<GridCell<P>> {
+        self.entities.get(&entity).copied()
+    }
+
+    /// Iterates over the entities inside `cell`.
+    pub fn entities_in(&self, cell: &GridCell<P>) -> impl Iterator<Item = Entity> + '_ {
+        self.cells.get(cell).into_iter().flatten().copied()
+    }
+
+    /// Iterates over every occupied cell, and the entities inside of it.
+    pub fn occupied_cells(&self) -> impl Iterator<Item = (&GridCell<P>, &HashSet<Entity>)> {
+        self.cells.iter()
+    }
+
+    /// The number of cells that contain at least one entity.
+    pub fn occupied_cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns `true` if any entity is inside `cell`.
+    pub fn is_occupied(&self, cell: &GridCell<P>) -> bool {
+        self.cells.contains_key(cell)
+    }
+
+    /// Iterates over the occupied cells within `radius` cells of `cell` along every axis, including
+    /// `cell` itself.
+    pub fn cells_within(
+        &self,
+        cell: GridCell<P>,
+        radius: u32,
+    ) -> impl Iterator<Item = (&GridCell<P>, &HashSet<Entity>)> {
+        let edge = 2 * radius as usize + 1;
+        let cube_volume = edge.saturating_mul(edge).saturating_mul(edge);
+        let r = radius as i64;
+
+        // Either visit every cell in the cube around `cell`, or every occupied cell, whichever is
+        // fewer.
+        let (cube, occupied) = if cube_volume <= self.cells.len() {
+            let cube = (-r..=r).flat_map(move |x| {
+                (-r..=r).flat_map(move |y| {
+                    (-r..=r).filter_map(move |z| {
+                        // Cells past the edge of the grid are skipped, rather than wrapping around.
+                        let offset = DVec3::new(x as f64, y as f64, z as f64);
+                        self.cells.get_key_value(&cell.checked_add_f64(offset)?)
+                    })
+                })
+            });
+            (Some(cube), None)
+        } else {
+            let occupied = self.cells.iter().filter(move |(other, _)| {
+                other.x.sub_f64(cell.x).abs() <= r as f64
+                    && other.y.sub_f64(cell.y).abs() <= r as f64
+                    && other.z.sub_f64(cell.z).abs() <= r as f64
+            });
+            (None, Some(occupied))
+        };
+        cube.into_iter()
+            .flatten()
+            .chain(occupied.into_iter().flatten())
+    }
+
+    /// Iterates over the occupied cells directly adjacent to `cell`, including diagonals.
+    pub fn neighbor_cells(
+        &self,
+        cell: GridCell<P>,
+    ) -> impl Iterator<Item = (&GridCell<P>, &HashSet<Entity>)> {
+        self.cells_within(cell, 1)
+            .filter(move |(other, _)| **other != cell)
+    }
+
+    /// Iterates over every entity within `radius` cells of `cell` along every axis.
+    pub fn entities_within(
+        &self,
+        cell: GridCell<P>,
+        radius: u32,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        self.cells_within(cell, radius)
+            .flat_map(|(_, entities)| entities.iter().copied())
+    }
+
+    fn insert(&mut self, entity: Entity, cell: GridCell<P>) {
+        if let Some(old_cell) = self.entities.insert(entity, cell) {
+            if old_cell == cell {
+                return;
+            }
+            self.remove_from_cell(entity, &old_cell);
+        }
+        self.cells.entry(cell).or_default().insert(entity);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(old_cell) = self.entities.remove(&entity) {
+            self.remove_from_cell(entity, &old_cell);
+        }
+    }
+
+    fn remove_from_cell(&mut self, entity: Entity, cell: &GridCell<P>) {
+        if let Some(entities) = self.cells.get_mut(cell) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.cells.remove(cell);
+            }
+        }
+    }
+}
+
+/// Updates the [`GridIndex`] with entities that changed cells, moved in or out of the root grid, or
+/// had their [`GridCell`] removed.
+///
+/// This runs again after the commands of [`CoreStage::PostUpdate`] are applied, see
+/// [`update_grid_index_after_commands`].
+pub fn update_grid_index<P: GridPrecision>(
+    mut index: ResMut<GridIndex<P>>,
+    changed: Query<
+        (Entity, &GridCell<P>, Option<&Parent>),
+        Or<(Changed<GridCell<P>>, Changed<Parent>)>,
+    >,
+    new_frames: Query<&Children, Added<GridCell<P>>>,
+    cells: Query<&GridCell<P>>,
+    children: Query<&Children>,
+    removed_cells: RemovedComponents<GridCell<P>>,
+    removed_parents: RemovedComponents<Parent>,
+) {
+    for entity in removed_cells.iter() {
+        index.remove(entity);
+        // The grid children of an entity that is no longer a grid entity are in the root grid now.
+        for child in children.get(entity).into_iter().flatten() {
+            if let Ok(cell) = cells.get(*child) {
+                index.insert(*child, *cell);
+            }
+        }
+    }
+    for (entity, cell, parent) in &changed {
+        match parent {
+            Some(parent) if cells.contains(parent.get()) => index.remove(entity),
+            _ => index.insert(entity, *cell),
+        }
+    }
+    // The grid children of a new grid entity are inside its reference frame now.
+    for child in new_frames.iter().flatten() {
+        index.remove(*child);
+    }
+    for entity in removed_parents.iter() {
+        if let Ok(cell) = cells.get(entity) {
+            index.insert(entity, *cell);
+        }
+    }
+}
+
+/// Runs [`update_grid_index`] again at the end of [`CoreStage::PostUpdate`], after its commands are
+/// applied. Entities despawned by those commands, like with
+/// [`GridOverflowPolicy::Despawn`](crate::overflow::GridOverflowPolicy::Despawn), would otherwise
+/// be left in the index, as removed components are cleared before the next update.
+pub fn update_grid_index_after_commands<P: GridPrecision>(
+    world: &mut World,
+    mut system: Local<Option<BoxedSystem>>,
+) {
+    let system = system.get_or_insert_with(|| {
+        let mut system: BoxedSystem = Box::new(IntoSystem::into_system(update_grid_index::<P>));
+        system.initialize(world);
+        system
+    });
+    system.run((), world);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{overflow::GridOverflowPolicy, FloatingOriginPlugin, FloatingOriginSettings};
+
+    #[test]
+    fn removals() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i8> {
+            settings: FloatingOriginSettings::default()
+                .with_overflow_policy(GridOverflowPolicy::Despawn),
+            ..default()
+        });
+        let edge = GridCell::new(i8::MAX, 0, 0);
+        let overflowing = app.world.spawn((TransformBundle::default(), edge)).id();
+        let frame = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i8>::ZERO))
+            .id();
+        let child = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i8>::ONE))
+            .id();
+        app.world.entity_mut(frame).push_children(&[child]);
+        app.update();
+        let index = app.world.resource::<GridIndex<i8>>();
+        assert_eq!(index.cell_of(overflowing), Some(edge));
+        assert_eq!(index.cell_of(child), None);
+
+        // Despawned by a command at the end of `PostUpdate`.
+        app.world
+            .get_mut::<Transform>(overflowing)
+            .unwrap()
+            .translation
+            .x = 1e6;
+        // Its child is in the root grid now.
+        app.world.entity_mut(frame).remove::<GridCell<i8>>();
+        app.update();
+        let index = app.world.resource::<GridIndex<i8>>();
+        assert!(app.world.get_entity(overflowing).is_none());
+        assert_eq!(index.cell_of(overflowing), None);
+        assert!(!index.is_occupied(&edge));
+        assert_eq!(index.cell_of(frame), None);
+        assert_eq!(index.cell_of(child), Some(GridCell::ONE));
+
+        // Back inside the frame.
+        app.world.entity_mut(frame).insert(GridCell::<i8>::ZERO);
+        app.update();
+        let index = app.world.resource::<GridIndex<i8>>();
+        assert_eq!(index.cell_of(child), None);
+        assert_eq!(
+            index.entities_within(GridCell::ZERO, 0).collect::<Vec<_>>(),
+            vec![frame]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Precise relative positions between grid entities.
+
+use bevy::{
+    ecs::system::SystemParam,
+    math::{DQuat, DVec3},
+    prelude::*,
+    render::view::RenderLayers,
+};
+
+use crate::{
+    precision::GridPrecision,
+    reference_frame::{locate, offset_from, Frame, FramePositions, ReferenceFrames, RotatingFrame},
+    FloatingOriginSettings, GridCell,
+};
+
+/// A read-only [`SystemParam`] for finding the exact offset between grid entities.
+///
+/// Offsets are computed from the [`GridCell`] and [`Transform`] of each entity, subtracting cells
+/// as integers before converting to `f64`, so they stay precise at any distance, unlike offsets
+/// between [`GlobalTransform`]s. Entities can be in different
+/// [reference frames](crate::reference_frame); offsets are always along the axes of the root grid.
+#[derive(SystemParam)]
+pub struct GridTransforms<'w, 's, P: GridPrecision> {
+    settings: Res<'w, FloatingOriginSettings>,
+    reference_frames: Res<'w, ReferenceFrames<P>>,
+    entities: Query<
+        'w,
+        's,
+        (
+            &'static GridCell<P>,
+            &'static Transform,
+            Option<&'static Parent>,
+            Option<&'static RotatingFrame>,
+            Option<&'static RenderLayers>,
+        ),
+    >,
+}
+
+impl<'w, 's, P: GridPrecision> GridTransforms<'w, 's, P> {
+    /// Returns the offset from `from` to `to`, or `None` if either is not a grid entity.
+    pub fn offset(&self, from: Entity, to: Entity) -> Option<DVec3> {
+        let from = self.locate(from)?;
+        let (cell, transform, parent, ..) = self.entities.get(to).ok()?;
+        Some(offset_from(
+            &self.settings,
+            Some(&from),
+            *cell,
+            transform.translation.as_dvec3(),
+            self.frame_of(parent),
+            |entity| self.frame(entity),
+        ))
+    }
+
+    /// Returns the distance between `from` and `to`, or `None` if either is not a grid entity.
+    pub fn distance(&self, from: Entity, to: Entity) -> Option<f64> {
+        self.offset(from, to).map(|offset| offset.length())
+    }
+
+    /// Returns the offset from the [`FloatingOrigin`](crate::FloatingOrigin) that `entity` is
+    /// rendered relative to, to `entity`. See [`ReferenceFrames::origin_for`].
+    pub fn offset_from_origin(&self, entity: Entity) -> Option<DVec3> {
+        let (.., layers) = self.entities.get(entity).ok()?;
+        let origin = self.reference_frames.origin_for(entity, layers)?;
+        self.offset(origin.entity(), entity)
+    }
+
+    /// Finds the position of `entity` in every frame between it and the root grid.
+    fn locate(&self, entity: Entity) -> Option<FramePositions<P>> {
+        let (cell, transform, parent, ..) = self.entities.get(entity).ok()?;
+        Some(locate(
+            &self.settings,
+            *cell,
+            transform.translation.as_dvec3(),
+            self.frame_of(parent),
+            |entity| self.frame(entity),
+        ))
+    }
+
+    /// Returns the frame defined by `entity`, from its current components rather than from the
+    /// last update of [`ReferenceFrames`].
+    fn frame(&self, entity: Entity) -> Option<Frame<P>> {
+        let (cell, transform, parent, rotating, _) = self.entities.get(entity).ok()?;
+        let rotation = match rotating {
+            Some(_) => transform.rotation.as_f64(),
+            None => DQuat::IDENTITY,
+        };
+        Some(Frame {
+            parent: self.frame_of(parent),
+            cell: *cell,
+            translation: transform.translation.as_dvec3(),
+            rotation,
+            changed: false,
+        })
+    }
+
+    /// The frame an entity with `parent` is inside of, if the parent is a grid entity.
+    fn frame_of(&self, parent: Option<&Parent>) -> Option<Entity> {
+        parent
+            .map(|parent| parent.get())
+            .filter(|parent| self.entities.contains(*parent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::{FloatingOrigin, FloatingOriginPlugin};
+
+    #[test]
+    fn offset_into_rotating_frame() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let origin = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                FloatingOrigin,
+            ))
+            .id();
+        let rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let child = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(1.0, 0.0, 0.0)),
+                GridCell::<i64>::new(1, 0, 0),
+            ))
+            .id();
+        app.world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_rotation(rotation)),
+                GridCell::<i64>::new(0, 1, 0),
+                RotatingFrame,
+            ))
+            .push_children(&[child]);
+        app.update();
+
+        let mut state = SystemState::<GridTransforms<i64>>::new(&mut app.world);
+        let transforms = state.get(&app.world);
+        let offset = transforms.offset(origin, child).unwrap();
+        assert!(offset.abs_diff_eq(DVec3::new(0.0, 20_001.0, 0.0), 1e-3));
+        let back = transforms.offset(child, origin).unwrap();
+        assert!(back.abs_diff_eq(-offset, 1e-6));
+        assert_eq!(transforms.offset_from_origin(child), Some(offset));
+
+        let global = app
+            .world
+            .get::<GlobalTransform>(child)
+            .unwrap()
+            .translation();
+        assert!(global.as_dvec3().abs_diff_eq(offset, 1e-3));
+    }
+}
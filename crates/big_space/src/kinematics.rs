@@ -0,0 +1,228 @@
+//! Moving grid entities with precise velocities.
+//!
+//! Adding `velocity * delta_time` to an `f32` [`Transform`] loses precision when the step is large,
+//! and a step can cross many grid cells at once. A grid entity with a [`GridVelocity`], and
+//! optionally a [`GridAcceleration`], is instead moved by [`integrate_motion`] with `f64` math, and
+//! the whole cells it crossed are added to its [`GridCell`] directly, sending a
+//! [`GridCellChanged`] event.
+
+use std::sync::Mutex;
+
+use bevy::{ecs::query::ReadOnlyWorldQuery, math::DVec3, prelude::*};
+
+use crate::{
+    events::GridCellChanged, fixed_point::FixedTranslation, fixed_timestep::Interpolated,
+    precision::GridPrecision, FloatingOriginSettings, GridCell,
+};
+
+/// The velocity of a grid entity, in units of length per second, along the axes of its grid.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GridVelocity(pub DVec3);
+
+/// The acceleration of a grid entity with a [`GridVelocity`], in units of length per second
+/// squared, along the axes of its grid.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GridAcceleration(pub DVec3);
+
+/// The components moved by [`integrate_motion`].
+pub(crate) type Motion<P> = (
+    Entity,
+    &'static mut GridCell<P>,
+    &'static mut Transform,
+    Option<&'static mut FixedTranslation>,
+    &'static mut GridVelocity,
+    Option<&'static GridAcceleration>,
+);
+
+/// Moves every grid entity with a [`GridVelocity`] by one [`Time`] step, after accelerating it by
+/// its [`GridAcceleration`].
+///
+/// The entity ends up recentered in the cell nearest its new position, and a [`GridCellChanged`]
+/// event is sent if that is a new cell. If the cell is past the edge of the grid, the entity is left for
+/// [`recenter_transform_on_grid`](crate::recenter_transform_on_grid) to apply the
+/// [`GridOverflowPolicy`](crate::overflow::GridOverflowPolicy).
+///
+/// [`Interpolated`] entities are moved at a fixed timestep by the
+/// [`FixedTimestepPlugin`](crate::fixed_timestep::FixedTimestepPlugin) instead.
+pub fn integrate_motion<P: GridPrecision>(
+    time: Res<Time>,
+    settings: Res<FloatingOriginSettings>,
+    mut entities: Query<Motion<P>, Without<Interpolated<P>>>,
+    mut cell_events: EventWriter<GridCellChanged<P>>,
+) {
+    integrate(
+        &settings,
+        time.delta_seconds_f64(),
+        &mut entities,
+        &mut cell_events,
+    );
+}
+
+/// Moves `entities` by `delta_seconds`, see [`integrate_motion`].
+pub(crate) fn integrate<P: GridPrecision, F: ReadOnlyWorldQuery>(
+    settings: &FloatingOriginSettings,
+    delta_seconds: f64,
+    entities: &mut Query<Motion<P>, F>,
+    cell_events: &mut EventWriter<GridCellChanged<P>>,
+) {
+    if delta_seconds == 0.0 {
+        return;
+    }
+    let cell_changes = Mutex::new(Vec::new());
+    entities.par_for_each_mut(
+        1024,
+        |(entity, mut cell, mut transform, fixed, mut velocity, acceleration)| {
+            if let Some(acceleration) = acceleration {
+                velocity.0 += acceleration.0 * delta_seconds;
+            }
+            let displacement = velocity.0 * delta_seconds;
+            if displacement == DVec3::ZERO {
+                return;
+            }
+
+            let old_cell = *cell;
+            match fixed {
+                Some(mut fixed) => {
+                    let position = fixed.as_dvec3() + displacement;
+                    match step(settings, &cell, position) {
+                        Some((new_cell, translation)) => {
+                            *cell = new_cell;
+                            *fixed = FixedTranslation::from_dvec3(translation);
+                        }
+                        None => *fixed = FixedTranslation::from_dvec3(position),
+                    }
+                }
+                None => {
+                    let position = transform.translation.as_dvec3() + displacement;
+                    match step(settings, &cell, position) {
+                        Some((new_cell, translation)) => {
+                            *cell = new_cell;
+                            transform.translation = translation.as_vec3();
+                        }
+                        None => transform.translation = position.as_vec3(),
+                    }
+                }
+            }
+            if *cell != old_cell {
+                cell_changes.lock().unwrap().push(GridCellChanged {
+                    entity,
+                    old_cell,
+                    new_cell: *cell,
+                });
+            }
+        },
+    );
+    cell_events.send_batch(cell_changes.into_inner().unwrap());
+}
+
+/// Like [`FloatingOriginSettings::checked_recenter`], but keeps the new translation in `f64`, and
+/// leaves the cell untouched if it is the nearest one.
+fn step<P: GridPrecision>(
+    settings: &FloatingOriginSettings,
+    cell: &GridCell<P>,
+    position: DVec3,
+) -> Option<(GridCell<P>, DVec3)> {
+    let edge_length = settings.grid_edge_length();
+    let delta = (position / edge_length).round();
+    if delta == DVec3::ZERO {
+        return Some((*cell, position));
+    }
+    Some((cell.checked_add_f64(delta)?, position - delta * edge_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::step_app, FloatingOriginPlugin};
+
+    #[test]
+    fn large_steps() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let settings = FloatingOriginSettings::default();
+        let edge = settings.grid_edge_length();
+
+        let speed_of_light = 299_792_458.0;
+        let ship = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(0.25, 0.0, 0.0)),
+                GridCell::<i64>::ZERO,
+                GridVelocity(DVec3::new(speed_of_light * 1e6, 0.0, -1.0)),
+            ))
+            .id();
+        let fixed = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                FixedTranslation::ZERO,
+                GridVelocity(DVec3::ZERO),
+                GridAcceleration(DVec3::new(0.0, 3.0 * edge, 0.0)),
+            ))
+            .id();
+
+        step_app(&mut app, 0.0);
+        step_app(&mut app, 1.0);
+
+        // A million light-seconds in a single step.
+        let cell = *app.world.get::<GridCell<i64>>(ship).unwrap();
+        let translation = app.world.get::<Transform>(ship).unwrap().translation;
+        let position = settings.grid_position_double(&cell) + translation.as_dvec3();
+        let expected = DVec3::new(speed_of_light * 1e6 + 0.25, 0.0, -1.0);
+        assert_eq!(cell.x, (expected.x / edge).round() as i64);
+        assert!((position - expected).abs().max_element() < 0.1);
+
+        // Accelerated to three cells per second, then moved for a second.
+        assert_eq!(
+            app.world.get::<GridCell<i64>>(fixed),
+            Some(&GridCell::new(0, 3, 0))
+        );
+        assert_eq!(
+            app.world.get::<FixedTranslation>(fixed),
+            Some(&FixedTranslation::ZERO)
+        );
+    }
+
+    #[test]
+    fn cell_changed_events() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let edge = FloatingOriginSettings::default().grid_edge_length();
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                GridVelocity(DVec3::new(0.0, 0.0, -2.0 * edge)),
+            ))
+            .id();
+        let mut reader = app
+            .world
+            .resource::<Events<GridCellChanged<i64>>>()
+            .get_reader();
+        let mut events = |app: &App| {
+            reader
+                .iter(app.world.resource())
+                .map(|event| (event.entity, event.old_cell, event.new_cell))
+                .collect::<Vec<_>>()
+        };
+
+        step_app(&mut app, 0.0);
+        assert!(events(&app).is_empty());
+        step_app(&mut app, 1.0);
+        assert_eq!(
+            events(&app),
+            [(entity, GridCell::ZERO, GridCell::new(0, 0, -2))]
+        );
+        step_app(&mut app, 0.25);
+        assert_eq!(
+            events(&app),
+            [(entity, GridCell::new(0, 0, -2), GridCell::new(0, 0, -3))]
+        );
+    }
+}
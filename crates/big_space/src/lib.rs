@@ -1,15 +1,60 @@
 //! Problem: objects far from the origin suffer from reduced precision.
-//! Solution: store object position relative to the current grid cell. Each grid cell should be about 10km on each edge to give 0.5mm precision at the extents.
-//! Store grid cells in an octree.
-//! When an object exceeds its boundary,
+//!
+//! Solution: store the position of each object as a [`GridCell`], and a [`Transform`] relative to
+//! the center of that cell. Each grid cell should be about 10km on each edge to give 0.5mm
+//! precision at the extents. When an object moves past the boundary of its cell, it is recentered
+//! into the cell it moved into, so its translation always stays small. [`GlobalTransform`]s are
+//! computed relative to a [`FloatingOrigin`], usually the camera, so everything near it is
+//! rendered precisely no matter how far it is from the center of the grid.
+//!
+//! Grids can be nested in [reference frames](reference_frame), like the surface of a planet that
+//! moves with it. Occupied cells are tracked in a [`GridIndex`](grid_index::GridIndex), which
+//! backs [spatial queries](spatial_query) and [collisions](collision), and the cells around the
+//! origin can be populated on demand with the [`streaming`] module. Grid entities can be saved in scenes through reflection, or with serde
+//! by enabling the `serde` feature; enable the `integer128` feature of `ron` as well to save `i128`
+//! or `u128` cells as RON.
+//!
+//! The [`FloatingOriginPlugin`] propagates transforms itself, but works alongside Bevy's
+//! `TransformPlugin`, which is part of `DefaultPlugins`, added in either order: anything Bevy's
+//! propagation writes to a grid entity is corrected in the same frame.
 
-use bevy::{math::DVec3, prelude::*, transform::TransformSystem};
-use std::marker::PhantomData;
+use bevy::{
+    ecs::system::BoxedSystem,
+    hierarchy::{check_hierarchy_component_has_valid_parent, ReportHierarchyIssue},
+    math::DVec3,
+    prelude::*,
+    render::view::RenderLayers,
+    transform::TransformSystem,
+    utils::HashSet,
+};
+use std::{marker::PhantomData, sync::Mutex};
 
+pub mod collision;
+pub mod commands;
+pub mod conversion;
 pub mod debug;
+pub mod events;
+pub mod far_field;
+pub mod fixed_point;
+pub mod fixed_timestep;
+pub mod grid_index;
+pub mod grid_transforms;
+pub mod kinematics;
+pub mod overflow;
 pub mod precision;
+pub mod ray;
+pub mod reference_frame;
+pub mod spatial_query;
+pub mod streaming;
 
+use events::*;
+use far_field::*;
+use fixed_point::*;
+use grid_index::*;
+use kinematics::*;
+use overflow::*;
 use precision::*;
+use reference_frame::*;
 
 #[derive(Default)]
 pub struct FloatingOriginPlugin<P: GridPrecision> {
@@ -19,51 +64,190 @@ pub struct FloatingOriginPlugin<P: GridPrecision> {
 
 impl<P: GridPrecision> Plugin for FloatingOriginPlugin<P> {
     fn build(&self, app: &mut App) {
+        // Bevy's propagation runs first, whichever plugin is added first, so anything it writes to
+        // a grid entity is corrected. Without `TransformPlugin`, this only logs a warning about an
+        // unknown label when the schedule is built.
+        let recenter_transform_on_grid = || {
+            recenter_transform_on_grid::<P>
+                .label(FloatingOriginSystem::RecenterGrid)
+                .label(TransformSystem::TransformPropagate)
+                .after(bevy::transform::transform_propagate_system)
+        };
         app.insert_resource(self.settings.clone())
             .register_type::<Transform>()
             .register_type::<GlobalTransform>()
+            .register_type::<P>()
             .register_type::<GridCell<P>>()
-            .add_plugin(ValidParentCheckPlugin::<GlobalTransform>::default())
+            .register_type::<FloatingOrigin>()
+            .register_type::<OriginPriority>()
+            .register_type::<FloatingOriginSettings>()
+            .register_type::<GridOverflowPolicy>()
+            .register_type::<OriginMode>()
+            .register_type::<RotatingFrame>()
+            .register_type::<FixedTranslation>()
+            .register_type::<FarField>()
+            .register_type::<Option<FarField>>()
+            .register_type::<GridVelocity>()
+            .register_type::<GridAcceleration>()
+            .add_system_to_stage(CoreStage::Last, check_hierarchy_without_transform_plugin)
+            .add_event::<GridOverflow<P>>()
+            .add_event::<GridCellChanged<P>>()
+            .add_event::<FloatingOriginShifted<P>>()
+            .init_resource::<ReferenceFrames<P>>()
+            .init_resource::<GridIndex<P>>()
+            .init_resource::<FarFieldQueue<P>>()
+            // Added by `TimePlugin` too, but without it, entities just don't move.
+            .init_resource::<Time>()
             // add transform systems to startup so the first update is "correct"
+            .add_startup_system_to_stage(StartupStage::PostStartup, recenter_transform_on_grid())
             .add_startup_system_to_stage(
                 StartupStage::PostStartup,
-                recenter_transform_on_grid::<P>
+                update_grid_index::<P>
+                    .label(FloatingOriginSystem::UpdateGridIndex)
+                    .after(FloatingOriginSystem::RecenterGrid),
+            )
+            .add_startup_system_to_stage(
+                StartupStage::PostStartup,
+                update_reference_frames::<P>
+                    .label(FloatingOriginSystem::UpdateReferenceFrames)
                     .label(TransformSystem::TransformPropagate)
-                    .before(update_global_from_grid::<P>),
+                    .after(FloatingOriginSystem::RecenterGrid),
             )
             .add_startup_system_to_stage(
                 StartupStage::PostStartup,
                 update_global_from_grid::<P>
+                    .label(FloatingOriginSystem::GridToGlobal)
                     .label(TransformSystem::TransformPropagate)
-                    .before(transform_propagate_system::<P>),
+                    .after(FloatingOriginSystem::UpdateGridIndex)
+                    .after(FloatingOriginSystem::UpdateReferenceFrames),
             )
             .add_startup_system_to_stage(
                 StartupStage::PostStartup,
-                transform_propagate_system::<P>.label(TransformSystem::TransformPropagate),
+                transform_propagate_system::<P>
+                    .label(FloatingOriginSystem::PropagateHierarchy)
+                    .label(TransformSystem::TransformPropagate)
+                    .after(FloatingOriginSystem::GridToGlobal),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                integrate_motion::<P>
+                    .label(FloatingOriginSystem::Integrate)
+                    .before(FloatingOriginSystem::RecenterGrid),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, recenter_transform_on_grid())
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_grid_index::<P>
+                    .label(FloatingOriginSystem::UpdateGridIndex)
+                    .after(FloatingOriginSystem::RecenterGrid),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_grid_index_after_commands::<P>.at_end(),
             )
             .add_system_to_stage(
                 CoreStage::PostUpdate,
-                recenter_transform_on_grid::<P>
+                update_reference_frames::<P>
+                    .label(FloatingOriginSystem::UpdateReferenceFrames)
                     .label(TransformSystem::TransformPropagate)
-                    .before(update_global_from_grid::<P>),
+                    .after(FloatingOriginSystem::RecenterGrid),
             )
             .add_system_to_stage(
                 CoreStage::PostUpdate,
                 update_global_from_grid::<P>
+                    .label(FloatingOriginSystem::GridToGlobal)
                     .label(TransformSystem::TransformPropagate)
-                    .before(transform_propagate_system::<P>),
+                    .after(FloatingOriginSystem::UpdateGridIndex)
+                    .after(FloatingOriginSystem::UpdateReferenceFrames),
             )
             .add_system_to_stage(
                 CoreStage::PostUpdate,
-                transform_propagate_system::<P>.label(TransformSystem::TransformPropagate),
+                transform_propagate_system::<P>
+                    .label(FloatingOriginSystem::PropagateHierarchy)
+                    .label(TransformSystem::TransformPropagate)
+                    .after(FloatingOriginSystem::GridToGlobal),
             );
     }
 }
 
+/// Warns about entities with a [`GlobalTransform`] whose parent has none, like Bevy's
+/// `TransformPlugin` does in debug builds.
+///
+/// `TransformPlugin` adds the check with a plugin that can only be added once, along with its
+/// [`ReportHierarchyIssue`] resource. The check is only run here while that resource is missing,
+/// so it is never run twice, whichever of the two plugins is added first.
+fn check_hierarchy_without_transform_plugin(
+    world: &mut World,
+    mut system: Local<Option<BoxedSystem>>,
+) {
+    if !cfg!(debug_assertions) || world.contains_resource::<ReportHierarchyIssue<GlobalTransform>>()
+    {
+        return;
+    }
+    let system = system.get_or_insert_with(|| {
+        let mut system: BoxedSystem = Box::new(IntoSystem::into_system(
+            check_hierarchy_component_has_valid_parent::<GlobalTransform>,
+        ));
+        system.initialize(world);
+        system
+    });
+    system.run((), world);
+}
+
+/// Labels for each phase of the [`FloatingOriginPlugin`]'s transform pipeline, which runs in
+/// [`CoreStage::PostUpdate`], and in [`StartupStage::PostStartup`] so the first frame is correct.
+///
+/// The phases run in this order:
+///
+/// 1. [`Integrate`](Self::Integrate), only in [`CoreStage::PostUpdate`]
+/// 2. [`RecenterGrid`](Self::RecenterGrid)
+/// 3. [`UpdateGridIndex`](Self::UpdateGridIndex) and
+///    [`UpdateReferenceFrames`](Self::UpdateReferenceFrames), in any order
+/// 4. [`GridToGlobal`](Self::GridToGlobal)
+/// 5. [`PropagateHierarchy`](Self::PropagateHierarchy)
+///
+/// If Bevy's `TransformPlugin` was added, before or after the [`FloatingOriginPlugin`], its
+/// propagation runs before [`RecenterGrid`](Self::RecenterGrid).
+///
+/// Every phase except [`UpdateGridIndex`](Self::UpdateGridIndex) is also labelled
+/// [`TransformSystem::TransformPropagate`], so systems that run after it see final
+/// [`GlobalTransform`]s. Systems that set the velocity of grid entities should run before
+/// [`Integrate`](Self::Integrate), and systems that move grid entities, like physics or
+/// interpolation, before [`RecenterGrid`](Self::RecenterGrid). Systems that place entities using
+/// their final grid cells, but before their [`GlobalTransform`]s are computed, should run between
+/// [`RecenterGrid`](Self::RecenterGrid) and [`GridToGlobal`](Self::GridToGlobal).
+#[derive(SystemLabel, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FloatingOriginSystem {
+    /// Moves grid entities by their [`GridVelocity`]. See [`integrate_motion`].
+    Integrate,
+    /// Moves grid entities into the cell nearest their translation. See
+    /// [`recenter_transform_on_grid`].
+    RecenterGrid,
+    /// Updates the [`GridIndex`] from the new cells. See [`update_grid_index`].
+    UpdateGridIndex,
+    /// Updates the [`ReferenceFrames`] and the position of each origin in them. See
+    /// [`update_reference_frames`].
+    UpdateReferenceFrames,
+    /// Computes the [`GlobalTransform`]s of grid entities. See [`update_global_from_grid`].
+    GridToGlobal,
+    /// Computes the [`GlobalTransform`]s of the children of grid entities, and of entities outside
+    /// the grid. See [`transform_propagate_system`].
+    PropagateHierarchy,
+}
+
+/// Configures the size of the grid, and how entities are moved between cells.
+///
+/// Lengths are stored as `f64`, so cell positions can be converted to floating point without
+/// losing precision to the edge length, even with very large cells or [`GridPrecision`]s.
 #[derive(Reflect, Resource, Clone)]
+#[reflect(Resource)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FloatingOriginSettings {
-    grid_edge_length: f32,
-    maximum_distance_from_origin: f32,
+    grid_edge_length: f64,
+    maximum_distance_from_origin: f64,
+    overflow_policy: GridOverflowPolicy,
+    origin_mode: OriginMode,
+    far_field: Option<FarField>,
 }
 
 impl FloatingOriginSettings {
@@ -71,42 +255,115 @@ impl FloatingOriginSettings {
     ///
     /// How far past the extents of a cell an entity must travel before a grid recentering occurs.
     /// This prevents entities from rapidly switching between cells when moving along a boundary.
-    pub fn new(grid_edge_length: f32, switching_threshold: f32) -> Self {
+    pub fn new(grid_edge_length: f64, switching_threshold: f64) -> Self {
         Self {
             grid_edge_length,
             maximum_distance_from_origin: grid_edge_length / 2.0 + switching_threshold,
+            overflow_policy: GridOverflowPolicy::default(),
+            origin_mode: OriginMode::default(),
+            far_field: None,
         }
     }
 
-    /// Converts the
+    /// Sets what happens to entities that move past the edge of the grid.
+    pub fn with_overflow_policy(mut self, overflow_policy: GridOverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// What happens to entities that move past the edge of the grid.
+    pub fn overflow_policy(&self) -> GridOverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Sets where the [`FloatingOrigin`] is placed relative to world zero.
+    pub fn with_origin_mode(mut self, origin_mode: OriginMode) -> Self {
+        self.origin_mode = origin_mode;
+        self
+    }
+
+    /// Where the [`FloatingOrigin`] is placed relative to world zero.
+    pub fn origin_mode(&self) -> OriginMode {
+        self.origin_mode
+    }
+
+    /// Updates the [`GlobalTransform`]s of far away entities over several frames when the origin
+    /// moves, instead of all at once. See [`far_field`].
+    pub fn with_far_field(mut self, far_field: FarField) -> Self {
+        self.far_field = Some(far_field);
+        self
+    }
+
+    /// Which entities are updated lazily when the origin moves, if any.
+    pub fn far_field(&self) -> Option<FarField> {
+        self.far_field
+    }
+
+    /// The edge length of a single grid cell.
+    pub fn grid_edge_length(&self) -> f64 {
+        self.grid_edge_length
+    }
+
+    /// How far an entity's translation can get from the center of its cell before it is recentered
+    /// on the grid.
+    pub fn maximum_distance_from_origin(&self) -> f64 {
+        self.maximum_distance_from_origin
+    }
+
+    /// Converts the grid cell `pos` and the translation of `transform` to a position relative to
+    /// the center of the grid.
     pub fn global_pos_double<P: GridPrecision>(
         &self,
         pos: &GridCell<P>,
         transform: &Transform,
+    ) -> DVec3 {
+        self.grid_position_double(pos) + transform.translation.as_dvec3()
+    }
+    /// Returns the position of the center of the grid cell `pos`, relative to the center of the
+    /// grid.
+    pub fn grid_position_double<P: GridPrecision>(&self, pos: &GridCell<P>) -> DVec3 {
+        DVec3 {
+            x: pos.x.as_f64() * self.grid_edge_length,
+            y: pos.y.as_f64() * self.grid_edge_length,
+            z: pos.z.as_f64() * self.grid_edge_length,
+        }
+    }
+
+    /// Returns the position of the center of the grid cell `to`, relative to the center of `from`.
+    ///
+    /// The cells are subtracted exactly with [`GridPrecision::sub_f64`] before being converted to
+    /// floating point, so the offset is precise for any two nearby cells, no matter how far they
+    /// are from the center of the grid.
+    pub fn grid_offset_double<P: GridPrecision>(
+        &self,
+        from: &GridCell<P>,
+        to: &GridCell<P>,
     ) -> DVec3 {
         DVec3 {
-            x: pos.x.as_f64() * self.grid_edge_length as f64 + transform.translation.x as f64,
-            y: pos.y.as_f64() * self.grid_edge_length as f64 + transform.translation.y as f64,
-            z: pos.z.as_f64() * self.grid_edge_length as f64 + transform.translation.z as f64,
+            x: to.x.sub_f64(from.x) * self.grid_edge_length,
+            y: to.y.sub_f64(from.y) * self.grid_edge_length,
+            z: to.z.sub_f64(from.z) * self.grid_edge_length,
         }
     }
+
+    /// Like [`Self::global_pos_double`], but rounded to `f32`. The position is computed in `f64`
+    /// and only rounded at the end.
     pub fn global_pos_single<P: GridPrecision>(
         &self,
         pos: &GridCell<P>,
         transform: &Transform,
     ) -> Vec3 {
-        Vec3 {
-            x: pos.x.as_f64() as f32 * self.grid_edge_length + transform.translation.x,
-            y: pos.y.as_f64() as f32 * self.grid_edge_length + transform.translation.y,
-            z: pos.z.as_f64() as f32 * self.grid_edge_length + transform.translation.z,
-        }
+        self.global_pos_double(pos, transform).as_vec3()
     }
 
+    /// Splits the position `input`, relative to the center of the grid, into the grid cell it is
+    /// in, and the translation of `input` relative to the center of that cell. The cell saturates at
+    /// the bounds of `P`.
     pub fn precise_translation<P: GridPrecision>(&self, input: DVec3) -> (GridCell<P>, Vec3) {
-        let l = self.grid_edge_length as f64;
+        let l = self.grid_edge_length;
         let DVec3 { x, y, z } = input;
 
-        if input.abs().max_element() < self.maximum_distance_from_origin as f64 {
+        if input.abs().max_element() < self.maximum_distance_from_origin {
             return (GridCell::default(), input.as_vec3());
         }
 
@@ -126,11 +383,74 @@ impl FloatingOriginSettings {
             Vec3::new(t_x as f32, t_y as f32, t_z as f32),
         )
     }
+
+    /// Like [`Self::precise_translation`], but returns `None` if the grid cell is out of the
+    /// bounds of `P`, instead of saturating.
+    pub fn checked_precise_translation<P: GridPrecision>(
+        &self,
+        input: DVec3,
+    ) -> Option<(GridCell<P>, Vec3)> {
+        let l = self.grid_edge_length;
+
+        if input.abs().max_element() < self.maximum_distance_from_origin {
+            return Some((GridCell::default(), input.as_vec3()));
+        }
+
+        let cell = (input / l).round();
+        let translation = input - cell * l;
+
+        Some((
+            GridCell {
+                x: P::checked_from_f64(cell.x)?,
+                y: P::checked_from_f64(cell.y)?,
+                z: P::checked_from_f64(cell.z)?,
+            },
+            translation.as_vec3(),
+        ))
+    }
+
+    /// Moves the position (`cell`, `translation`) into the cell nearest to it, if `translation` is
+    /// farther than [`Self::maximum_distance_from_origin`] from the center of `cell`. Returns
+    /// `None` if the new cell is out of the bounds of `P`.
+    ///
+    /// Unlike [`Self::precise_translation`], the cell is moved relative to its current value, so
+    /// this also works for unsigned [`GridPrecision`]s.
+    pub fn checked_recenter<P: GridPrecision>(
+        &self,
+        cell: &GridCell<P>,
+        translation: DVec3,
+    ) -> Option<(GridCell<P>, Vec3)> {
+        if translation.abs().max_element() <= self.maximum_distance_from_origin {
+            return Some((*cell, translation.as_vec3()));
+        }
+        let delta = (translation / self.grid_edge_length).round();
+        Some((
+            cell.checked_add_f64(delta)?,
+            (translation - delta * self.grid_edge_length).as_vec3(),
+        ))
+    }
+
+    /// Like [`Self::checked_recenter`], but clamps the cell to the bounds of `P`, discarding any
+    /// movement past the edge of the grid.
+    pub fn recenter<P: GridPrecision>(
+        &self,
+        cell: &GridCell<P>,
+        translation: DVec3,
+    ) -> (GridCell<P>, Vec3) {
+        if translation.abs().max_element() <= self.maximum_distance_from_origin {
+            return (*cell, translation.as_vec3());
+        }
+        let delta = (translation / self.grid_edge_length).round();
+        (
+            cell.saturating_add_f64(delta),
+            (translation - delta * self.grid_edge_length).as_vec3(),
+        )
+    }
 }
 
 impl Default for FloatingOriginSettings {
     fn default() -> Self {
-        Self::new(10_000f32, 100f32)
+        Self::new(10_000f64, 100f64)
     }
 }
 
@@ -159,6 +479,11 @@ pub struct PreciseSpatialBundle<P: GridPrecision> {
 /// - i32: 0.0045 light years = ~4 times the width of the solar system
 /// - i64: 19.5 million light years = ~100 times the width of the milky way galaxy
 /// - i128: 3.6e+26 light years = ~3.9e+15 times the width of the observable universe
+/// - [`I256`]: 1.2e+65 light years
+///
+/// The unsigned integer types are also supported. They cover the same volume as the signed type of
+/// the same size, but cell `(0, 0, 0)` is in the corner of the grid instead of the center, so
+/// entities can only be placed at positive coordinates.
 ///
 /// where
 ///
@@ -175,6 +500,7 @@ pub struct PreciseSpatialBundle<P: GridPrecision> {
 ///
 #[derive(Component, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Reflect)]
 #[reflect(Component, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GridCell<P: GridPrecision> {
     pub x: P,
     pub y: P,
@@ -196,11 +522,66 @@ impl<P: GridPrecision> GridCell<P> {
         y: P::ONE,
         z: P::ONE,
     };
+
+    /// Adds two cells, returning `None` if any axis overflows.
+    pub fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        Some(GridCell {
+            x: self.x.checked_add(rhs.x)?,
+            y: self.y.checked_add(rhs.y)?,
+            z: self.z.checked_add(rhs.z)?,
+        })
+    }
+
+    /// Subtracts two cells, returning `None` if any axis overflows.
+    pub fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        Some(GridCell {
+            x: self.x.checked_sub(rhs.x)?,
+            y: self.y.checked_sub(rhs.y)?,
+            z: self.z.checked_sub(rhs.z)?,
+        })
+    }
+
+    /// Moves the cell by the whole number of cells in `delta` along each axis, returning `None` if
+    /// any axis overflows. See [`GridPrecision::checked_add_f64`].
+    pub fn checked_add_f64(&self, delta: DVec3) -> Option<Self> {
+        Some(GridCell {
+            x: self.x.checked_add_f64(delta.x)?,
+            y: self.y.checked_add_f64(delta.y)?,
+            z: self.z.checked_add_f64(delta.z)?,
+        })
+    }
+
+    /// Like [`Self::checked_add_f64`], but clamps each axis to the bounds of `P` instead of
+    /// overflowing.
+    pub fn saturating_add_f64(&self, delta: DVec3) -> Self {
+        GridCell {
+            x: self.x.saturating_add_f64(delta.x),
+            y: self.y.saturating_add_f64(delta.y),
+            z: self.z.saturating_add_f64(delta.z),
+        }
+    }
+
+    /// Adds two cells, clamping each axis to the bounds of `P` instead of overflowing.
+    pub fn saturating_add(&self, rhs: &Self) -> Self {
+        GridCell {
+            x: self.x.saturating_add(rhs.x),
+            y: self.y.saturating_add(rhs.y),
+            z: self.z.saturating_add(rhs.z),
+        }
+    }
 }
+
+/// Panics on overflow in debug builds, and wraps around in release builds, like integer addition.
+/// Use [`GridCell::checked_add`] or [`GridCell::saturating_add`] to handle overflow.
 impl<P: GridPrecision> std::ops::Add for GridCell<P> {
     type Output = GridCell<P>;
 
     fn add(self, rhs: Self) -> Self::Output {
+        if cfg!(debug_assertions) {
+            return self
+                .checked_add(&rhs)
+                .expect("attempt to add grid cells with overflow");
+        }
         GridCell {
             x: self.x.wrapping_add(rhs.x),
             y: self.y.wrapping_add(rhs.y),
@@ -208,10 +589,17 @@ impl<P: GridPrecision> std::ops::Add for GridCell<P> {
         }
     }
 }
+/// Panics on overflow in debug builds, and wraps around in release builds, like integer
+/// subtraction. Use [`GridCell::checked_sub`] to handle overflow.
 impl<P: GridPrecision> std::ops::Sub for GridCell<P> {
     type Output = GridCell<P>;
 
     fn sub(self, rhs: Self) -> Self::Output {
+        if cfg!(debug_assertions) {
+            return self
+                .checked_sub(&rhs)
+                .expect("attempt to subtract grid cells with overflow");
+        }
         GridCell {
             x: self.x.wrapping_sub(rhs.x),
             y: self.y.wrapping_sub(rhs.y),
@@ -234,69 +622,448 @@ impl<P: GridPrecision> std::ops::Sub for &GridCell<P> {
     }
 }
 
-#[derive(Component, Reflect)]
+/// Marks an entity as a floating origin. The [`GlobalTransform`] of every grid entity is computed
+/// relative to the grid cell of an origin, or the origin itself depending on the [`OriginMode`], so
+/// rendering stays precise around it.
+///
+/// Any number of origins can exist at the same time, e.g. one per camera for picture-in-picture
+/// views of different star systems. Each grid entity uses the origin with the highest
+/// [`OriginPriority`] that shares one of its [`RenderLayers`], or the default layer if it has none;
+/// an origin is always relative to itself. Give each camera its own render layers, and the
+/// entities it should see matching layers, to render every view with its own origin.
+///
+/// Views of the same entities from different places are not supported. An entity only has one
+/// [`GlobalTransform`], so an entity visible to several origins is only rendered in the right place
+/// by the cameras of the origin it uses. Other origins only see it correctly if they are in the
+/// same cell in [`OriginMode::Grid`], or at the same position in [`OriginMode::CameraRelative`].
+/// Split-screen views of one world from different cells would need a copy of every shared entity
+/// for each origin.
+///
+/// If there are no origins, grid entities are placed relative to the center of the root grid.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FloatingOrigin;
 
+/// Decides which [`FloatingOrigin`] a grid entity uses when it shares [`RenderLayers`] with
+/// several of them. The origin with the highest priority wins; origins without this component have
+/// a priority of `0`. Ties are broken by entity, and logged as a warning.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OriginPriority(pub i32);
+
+/// Where a [`FloatingOrigin`] is placed relative to world zero, i.e. what every [`GlobalTransform`]
+/// is relative to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OriginMode {
+    /// World zero is the center of the origin's grid cell. [`GlobalTransform`]s only need to be
+    /// recomputed when the origin changes cells, but the origin itself can be up to
+    /// [`FloatingOriginSettings::maximum_distance_from_origin`] away from zero.
+    #[default]
+    Grid,
+    /// World zero is the exact position of the origin, i.e. camera-relative rendering. Every
+    /// [`GlobalTransform`] using the origin is recomputed whenever it moves, so rendering is as
+    /// precise as possible around the origin, even with very small cells.
+    CameraRelative,
+}
+
 /// If an entity's transform becomes larger than the specified limit, it is relocated to the next
 /// grid cell to reduce the size of the transform.
+///
+/// Entities in nested [reference frames](crate::reference_frame) are recentered on the grid of the
+/// frame they are inside of.
+///
+/// Entities with a [`FixedTranslation`] are recentered with integer math instead, and the
+/// translation of their [`Transform`] is derived from it.
+///
+/// A [`GridCellChanged`] event is sent for every entity that moves into a new cell.
+///
+/// Entities that would move past the edge of the grid are handled with the
+/// [`GridOverflowPolicy`] in the [`FloatingOriginSettings`], and reported with a [`GridOverflow`]
+/// event.
 pub fn recenter_transform_on_grid<P: GridPrecision>(
+    mut commands: Commands,
     settings: Res<FloatingOriginSettings>,
-    mut query: Query<(&mut GridCell<P>, &mut Transform), (Changed<Transform>, Without<Parent>)>,
+    mut query: Query<
+        (
+            Entity,
+            &mut GridCell<P>,
+            &mut Transform,
+            Option<&mut FixedTranslation>,
+        ),
+        Or<(Changed<Transform>, Changed<FixedTranslation>)>,
+    >,
+    mut overflow_events: EventWriter<GridOverflow<P>>,
+    mut cell_events: EventWriter<GridCellChanged<P>>,
 ) {
-    query.par_for_each_mut(1024, |(mut grid_pos, mut transform)| {
-        if transform.as_ref().translation.abs().max_element()
+    let overflows = Mutex::new(Vec::new());
+    let cell_changes = Mutex::new(Vec::new());
+
+    query.par_for_each_mut(1024, |(entity, mut grid_pos, mut transform, fixed)| {
+        let old_cell = *grid_pos;
+        if let Some(mut fixed) = fixed {
+            let recentered = fixed.checked_recenter(&settings, &grid_pos);
+            let (cell, translation) = recentered.unwrap_or_else(|| {
+                overflows.lock().unwrap().push(GridOverflow {
+                    entity,
+                    cell: *grid_pos,
+                    translation: fixed.as_vec3(),
+                });
+                match settings.overflow_policy {
+                    GridOverflowPolicy::Clamp => fixed.recenter(&settings, &grid_pos),
+                    _ => (*grid_pos, *fixed),
+                }
+            });
+            // When clamped at the edge of the grid, the cell stays the same but the translation is
+            // still pulled back.
+            if cell != *grid_pos {
+                *grid_pos = cell;
+            }
+            if translation != *fixed {
+                *fixed = translation;
+            }
+            // The fixed translation is the source of truth, even if only the transform changed.
+            if transform.translation != fixed.as_vec3() {
+                transform.translation = fixed.as_vec3();
+            }
+        } else if transform.as_ref().translation.abs().max_element() as f64
             > settings.maximum_distance_from_origin
         {
-            let (grid_cell_delta, translation) =
-                settings.precise_translation(transform.as_ref().translation.as_dvec3());
-            *grid_pos = *grid_pos + grid_cell_delta;
-            transform.translation = translation;
+            let input = transform.as_ref().translation.as_dvec3();
+            let recentered = settings.checked_recenter(&grid_pos, input);
+
+            if let Some((cell, translation)) = recentered {
+                *grid_pos = cell;
+                transform.translation = translation;
+            } else {
+                overflows.lock().unwrap().push(GridOverflow {
+                    entity,
+                    cell: *grid_pos,
+                    translation: transform.translation,
+                });
+                if settings.overflow_policy == GridOverflowPolicy::Clamp {
+                    let (cell, translation) = settings.recenter(&grid_pos, input);
+                    *grid_pos = cell;
+                    transform.translation = translation;
+                }
+            }
+        }
+
+        if *grid_pos != old_cell {
+            cell_changes.lock().unwrap().push(GridCellChanged {
+                entity,
+                old_cell,
+                new_cell: *grid_pos,
+            });
         }
     });
+
+    for overflow in overflows.into_inner().unwrap() {
+        match settings.overflow_policy {
+            GridOverflowPolicy::Panic => panic!(
+                "{:?} moved past the edge of the grid, from cell {:?} with translation {}",
+                overflow.entity, overflow.cell, overflow.translation
+            ),
+            GridOverflowPolicy::Despawn => commands.entity(overflow.entity).despawn_recursive(),
+            GridOverflowPolicy::Clamp | GridOverflowPolicy::EmitEvent => (),
+        }
+        overflow_events.send(overflow);
+    }
+    cell_events.send_batch(cell_changes.into_inner().unwrap());
 }
 
+/// Computes the [`GlobalTransform`] of every grid entity, relative to the [`FloatingOrigin`]. See
+/// [`OriginMode`].
+///
+/// Entities are only updated if they, or any [reference frame](crate::reference_frame) they are
+/// inside of, moved, if their [`RenderLayers`] or [`Parent`] changed, or if another system, like
+/// Bevy's own transform propagation, changed their [`GlobalTransform`]. If the origin moved
+/// relative to any of its frames, everything using that origin is updated.
+///
+/// Each entity is placed relative to the origin chosen by [`ReferenceFrames::origin_for`].
+///
+/// If the [`FloatingOriginSettings`] have a [`FarField`], only the entities that changed are
+/// visited, and only the entities in the cells of the [`GridIndex`] near a moved origin are updated
+/// because of it. When the origin moves to another cell, the other occupied cells are added to the
+/// [`FarFieldQueue`] instead, and the [`GlobalTransform`]s of their entities stay stale until they
+/// are taken from the queue, a few each time this system runs.
 pub fn update_global_from_grid<P: GridPrecision>(
     settings: Res<FloatingOriginSettings>,
-    origin: Query<(&GridCell<P>, Changed<GridCell<P>>), With<FloatingOrigin>>,
-    mut entities: ParamSet<(
-        Query<
-            (&Transform, &mut GlobalTransform, &GridCell<P>),
-            Or<(Changed<GridCell<P>>, Changed<Transform>)>,
-        >,
-        Query<(&Transform, &mut GlobalTransform, &GridCell<P>)>,
-    )>,
+    reference_frames: Res<ReferenceFrames<P>>,
+    grid_index: Res<GridIndex<P>>,
+    mut far_field_queue: ResMut<FarFieldQueue<P>>,
+    mut entities: GridToGlobalParams<P>,
+    frames: Query<&Children, With<GridCell<P>>>,
+    (removed_layers, removed_parents): (RemovedComponents<RenderLayers>, RemovedComponents<Parent>),
 ) {
-    let (origin_cell, origin_grid_pos_changed) = origin.single();
+    let update_all = reference_frames.origins_changed();
 
-    if origin_grid_pos_changed {
-        let mut all_entities = entities.p1();
-        all_entities.par_for_each_mut(1024, |(local, global, entity_cell)| {
-            update_global_from_cell_local(&settings, entity_cell, origin_cell, local, global);
-        });
-    } else {
-        let mut moved_cell_entities = entities.p0();
-        moved_cell_entities.par_for_each_mut(1024, |(local, global, entity_cell)| {
-            update_global_from_cell_local(&settings, entity_cell, origin_cell, local, global);
-        });
+    let far_field = match settings.far_field() {
+        Some(far_field) => far_field,
+        None => {
+            // Entities that lost their render layers may need to use a different origin.
+            let removed_layers: HashSet<Entity> = removed_layers.iter().collect();
+            // Entities that left their parent may have left a reference frame.
+            let removed_parents: HashSet<Entity> = removed_parents.iter().collect();
+            let mut entities = entities.p1();
+            entities.par_for_each_mut(
+                1024,
+                |(entity, layers, local, transform_changed, global, cell, cell_changed, parent)| {
+                    let frame = reference_frames.frame_of(parent.map(|(parent, _)| parent));
+                    let layers_changed =
+                        matches!(layers, Some((_, true))) || removed_layers.contains(&entity);
+                    let parent_changed =
+                        matches!(parent, Some((_, true))) || removed_parents.contains(&entity);
+                    let origin =
+                        reference_frames.origin_for(entity, layers.map(|(layers, _)| layers));
+                    let moved = transform_changed
+                        || cell_changed
+                        || layers_changed
+                        || parent_changed
+                        || global.is_changed()
+                        || reference_frames.frame_changed(frame);
+                    let origin_moved = match origin {
+                        Some(origin) => update_all || origin.is_changed(),
+                        None => update_all,
+                    };
+                    if !moved && !origin_moved {
+                        return;
+                    }
+                    let translation = reference_frames.relative_to_origin(
+                        &settings,
+                        origin,
+                        *cell,
+                        local.translation.as_dvec3(),
+                        frame,
+                    );
+                    update_global_from_cell_local(
+                        &reference_frames,
+                        frame,
+                        translation,
+                        local,
+                        global,
+                    );
+                },
+            );
+
+            // Anything left over from before the far field was removed is updated at once.
+            far_field_queue.clear_origins();
+            while let Some(cell) = far_field_queue.pop() {
+                for entity in grid_index.entities_in(&cell) {
+                    update_global_in_frame(
+                        &settings,
+                        &reference_frames,
+                        &mut entities,
+                        &frames,
+                        entity,
+                    );
+                }
+            }
+            return;
+        }
+    };
+
+    // Only the entities that changed are visited. Updating an entity also updates everything in
+    // its reference frame, so entities inside a frame that moved are updated too.
+    let changed: Vec<Entity> = entities
+        .p0()
+        .iter()
+        .chain(removed_layers.iter())
+        .chain(removed_parents.iter())
+        .collect();
+    let mut entities = entities.p1();
+    let mut update = |entity| {
+        update_global_in_frame(&settings, &reference_frames, &mut entities, &frames, entity)
+    };
+    for entity in changed {
+        update(entity);
+    }
+
+    // The entities that need updating because their origin moved are found through the grid
+    // index.
+    if update_all {
+        far_field_queue.clear_origins();
+    }
+    for origin in reference_frames.origins() {
+        if !update_all && !origin.is_changed() {
+            continue;
+        }
+        let origin_cell = origin.root_cell(&settings);
+        for entity in grid_index.entities_within(origin_cell, far_field.cell_radius) {
+            update(entity);
+        }
+        // Far cells are only queued again once the origin moves to another cell. Moving within
+        // its cell, like in `OriginMode::CameraRelative`, barely moves anything far away on
+        // screen, and would otherwise keep the queue from ever emptying.
+        if !far_field_queue.queue_origin(origin.entity(), origin_cell) {
+            continue;
+        }
+        // Nearer cells are updated first.
+        let mut far_cells: Vec<_> = grid_index
+            .occupied_cells()
+            .map(|(cell, _)| *cell)
+            .filter(|cell| far_field.is_far(&origin_cell, cell))
+            .map(|cell| {
+                let distance = settings
+                    .grid_offset_double(&origin_cell, &cell)
+                    .length_squared();
+                (distance, cell)
+            })
+            .collect();
+        far_cells.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        far_field_queue.extend(far_cells.into_iter().map(|(_, cell)| cell));
+    }
+
+    let mut updated = 0;
+    while updated < far_field.entities_per_update {
+        let cell = match far_field_queue.pop() {
+            Some(cell) => cell,
+            None => break,
+        };
+        // Entities that left the cell, or were despawned, since it was queued are skipped.
+        for entity in grid_index.entities_in(&cell) {
+            updated += update(entity);
+        }
     }
 }
 
-fn update_global_from_cell_local<P: GridPrecision>(
+/// The grid entities [`update_global_from_grid`] visits when there is a [`FarField`], and every
+/// grid entity.
+type GridToGlobalParams<'w, 's, P> = ParamSet<
+    'w,
+    's,
+    (
+        Query<'w, 's, Entity, GridToGlobalChanged<P>>,
+        GridToGlobalQuery<'w, 's, P>,
+    ),
+>;
+
+/// The grid entities [`update_global_from_grid`] visits when there is a [`FarField`].
+type GridToGlobalChanged<P> = (
+    With<GridCell<P>>,
+    Or<(
+        Changed<Transform>,
+        Changed<GridCell<P>>,
+        Changed<GlobalTransform>,
+        Changed<Parent>,
+        Changed<RenderLayers>,
+    )>,
+);
+
+type GridToGlobalQuery<'w, 's, P> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<(&'static RenderLayers, Changed<RenderLayers>)>,
+        &'static Transform,
+        Changed<Transform>,
+        &'static mut GlobalTransform,
+        &'static GridCell<P>,
+        Changed<GridCell<P>>,
+        Option<(&'static Parent, Changed<Parent>)>,
+    ),
+>;
+
+/// Recomputes the [`GlobalTransform`] of the grid entity `entity`, and of every grid entity inside
+/// its reference frame, relative to the origin each of them uses. Returns the number of entities
+/// updated.
+fn update_global_in_frame<P: GridPrecision>(
     settings: &FloatingOriginSettings,
-    entity_cell: &GridCell<P>,
-    origin_cell: &GridCell<P>,
+    reference_frames: &ReferenceFrames<P>,
+    entities: &mut GridToGlobalQuery<P>,
+    frames: &Query<&Children, With<GridCell<P>>>,
+    entity: Entity,
+) -> usize {
+    let (entity, layers, local, _, global, entity_cell, _, parent) = match entities.get_mut(entity)
+    {
+        Ok(item) => item,
+        Err(_) => return 0,
+    };
+    let frame = reference_frames.frame_of(parent.map(|(parent, _)| parent));
+    let origin = reference_frames.origin_for(entity, layers.map(|(layers, _)| layers));
+    let translation = reference_frames.relative_to_origin(
+        settings,
+        origin,
+        *entity_cell,
+        local.translation.as_dvec3(),
+        frame,
+    );
+    let local = *local;
+    update_global_from_cell_local(reference_frames, frame, translation, &local, global);
+
+    let mut updated = 1;
+    for child in frames.get(entity).into_iter().flatten() {
+        updated += update_global_in_frame(settings, reference_frames, entities, frames, *child);
+    }
+    updated
+}
+
+/// Sets `global` from the `translation` of the entity relative to the origin, and its `local`
+/// rotation and scale inside `frame`.
+fn update_global_from_cell_local<P: GridPrecision>(
+    reference_frames: &ReferenceFrames<P>,
+    frame: Option<Entity>,
+    translation: DVec3,
     local: &Transform,
     mut global: Mut<GlobalTransform>,
 ) {
-    let grid_cell_delta = entity_cell - origin_cell;
-    *global = local
-        .clone()
-        .with_translation(settings.global_pos_single(&grid_cell_delta, local))
-        .into();
+    let rotation = reference_frames.orientation(frame) * local.rotation.as_f64();
+    *global = Transform {
+        translation: translation.as_vec3(),
+        rotation: rotation.as_f32(),
+        scale: local.scale,
+    }
+    .into();
+}
+
+/// An entity's [`Parent`] does not match the entity whose [`Children`] it was found in. This
+/// probably means that the hierarchy has been improperly maintained, or contains a cycle.
+///
+/// The [`GlobalTransform`]s of the entity and its descendants are not updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MalformedHierarchy {
+    /// The entity whose [`Parent`] is wrong.
+    pub entity: Entity,
+    /// The entity that lists `entity` in its [`Children`].
+    pub expected_parent: Entity,
+    /// The [`Parent`] of `entity`.
+    pub parent: Entity,
 }
 
+impl std::fmt::Display for MalformedHierarchy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "malformed hierarchy: {:?} is a child of {:?}, but its parent is {:?}",
+            self.entity, self.expected_parent, self.parent
+        )
+    }
+}
+
+impl std::error::Error for MalformedHierarchy {}
+
+type TransformQuery<'w, 's, P> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        Changed<Transform>,
+        &'static mut GlobalTransform,
+    ),
+    (With<Parent>, Without<GridCell<P>>),
+>;
+
+type ChildrenQuery<'w, 's> =
+    Query<'w, 's, (&'static Children, Changed<Children>), (With<Parent>, With<GlobalTransform>)>;
+
 /// Update [`GlobalTransform`] component of entities based on entity hierarchy and
 /// [`Transform`] component.
+///
+/// Hierarchies are propagated in parallel, one task per root. Any [`MalformedHierarchy`] found is
+/// logged as a warning, and the affected branch is skipped.
 pub fn transform_propagate_system<P: GridPrecision>(
     origin_moved: Query<(), (Changed<GridCell<P>>, With<FloatingOrigin>)>,
     mut root_query_no_grid: Query<
@@ -309,114 +1076,504 @@ pub fn transform_propagate_system<P: GridPrecision>(
         ),
         (Without<GridCell<P>>, Without<Parent>),
     >,
-    mut root_query_grid: Query<
+    root_query_grid: Query<
         (
             Option<(&Children, Changed<Children>)>,
-            Changed<Transform>,
-            Changed<GridCell<P>>,
+            Changed<GlobalTransform>,
             &GlobalTransform,
             Entity,
         ),
-        (With<GridCell<P>>, Without<Parent>),
+        With<GridCell<P>>,
     >,
-    mut transform_query: Query<(
-        &Transform,
-        Changed<Transform>,
-        &mut GlobalTransform,
-        &Parent,
-    )>,
-    children_query: Query<(&Children, Changed<Children>), (With<Parent>, With<GlobalTransform>)>,
+    transform_query: TransformQuery<P>,
+    parent_query: Query<&Parent>,
+    children_query: ChildrenQuery,
 ) {
     let origin_cell_changed = !origin_moved.is_empty();
+    let errors = Mutex::new(Vec::new());
+    let propagate_children =
+        |global_transform: &GlobalTransform, children: &Children, entity: Entity, changed: bool| {
+            for child in children {
+                // SAFETY: each root is visited by only one task. `propagate_recursive` checks that
+                // every child's `Parent` is the entity it was reached from before getting its
+                // `GlobalTransform`, so with each entity having exactly one parent, no entity is
+                // visited twice.
+                let result = unsafe {
+                    propagate_recursive(
+                        global_transform,
+                        &transform_query,
+                        &parent_query,
+                        &children_query,
+                        *child,
+                        entity,
+                        changed,
+                    )
+                };
+                if let Err(error) = result {
+                    errors.lock().unwrap().push(error);
+                }
+            }
+        };
 
-    for (children, transform, transform_changed, mut global_transform, entity) in
-        root_query_no_grid.iter_mut()
-    {
-        let mut changed = transform_changed || origin_cell_changed;
+    root_query_no_grid.par_for_each_mut(
+        1024,
+        |(children, transform, transform_changed, mut global_transform, entity)| {
+            let mut changed = transform_changed || origin_cell_changed;
 
-        if transform_changed {
-            *global_transform = GlobalTransform::from(*transform);
-        }
+            if transform_changed {
+                *global_transform = GlobalTransform::from(*transform);
+            }
 
-        if let Some((children, changed_children)) = children {
-            // If our `Children` has changed, we need to recalculate everything below us
-            changed |= changed_children;
-            for child in children {
-                let _ = propagate_recursive(
-                    &global_transform,
-                    &mut transform_query,
-                    &children_query,
-                    *child,
-                    entity,
-                    changed,
-                );
+            if let Some((children, changed_children)) = children {
+                // If our `Children` has changed, we need to recalculate everything below us
+                changed |= changed_children;
+                propagate_children(&global_transform, children, entity, changed);
             }
-        }
-    }
+        },
+    );
 
-    for (children, cell_changed, transform_changed, global_transform, entity) in
-        root_query_grid.iter_mut()
-    {
-        let mut changed = transform_changed || cell_changed || origin_cell_changed;
+    // Every grid entity, including those in nested reference frames, is the root of its own
+    // hierarchy. Their `GlobalTransform`s have already been computed from the grid, including when
+    // the origin moved, so only changed ones are propagated. Far entities that are still waiting in
+    // the `FarFieldQueue` are skipped until they are updated.
+    root_query_grid.par_for_each(
+        1024,
+        |(children, global_changed, global_transform, entity)| {
+            let mut changed = global_changed;
 
-        if let Some((children, changed_children)) = children {
-            // If our `Children` has changed, we need to recalculate everything below us
-            changed |= changed_children;
-            for child in children {
-                let _ = propagate_recursive(
-                    &global_transform,
-                    &mut transform_query,
-                    &children_query,
-                    *child,
-                    entity,
-                    changed,
-                );
+            if let Some((children, changed_children)) = children {
+                // If our `Children` has changed, we need to recalculate everything below us
+                changed |= changed_children;
+                propagate_children(global_transform, children, entity, changed);
             }
-        }
+        },
+    );
+
+    for error in errors.into_inner().unwrap() {
+        warn!("{error}");
     }
 }
 
-fn propagate_recursive(
+/// # Safety
+///
+/// No other call may be accessing the [`GlobalTransform`] of `entity`, or of any of its
+/// descendants, at the same time. Only entities whose [`Parent`] is `expected_parent` are accessed,
+/// so this holds as long as the caller has exclusive access to `expected_parent`.
+unsafe fn propagate_recursive<P: GridPrecision>(
     parent: &GlobalTransform,
-    transform_query: &mut Query<(
-        &Transform,
-        Changed<Transform>,
-        &mut GlobalTransform,
-        &Parent,
-    )>,
-    children_query: &Query<(&Children, Changed<Children>), (With<Parent>, With<GlobalTransform>)>,
+    transform_query: &TransformQuery<P>,
+    parent_query: &Query<&Parent>,
+    children_query: &ChildrenQuery,
     entity: Entity,
     expected_parent: Entity,
     mut changed: bool,
-    // We use a result here to use the `?` operator. Ideally we'd use a try block instead
-) -> Result<(), ()> {
-    let global_matrix = {
-        let (transform, transform_changed, mut global_transform, child_parent) =
-            transform_query.get_mut(entity).map_err(drop)?;
-        // Note that for parallelising, this check cannot occur here, since there is an `&mut GlobalTransform` (in global_transform)
-        assert_eq!(
-            child_parent.get(), expected_parent,
-            "Malformed hierarchy. This probably means that your hierarchy has been improperly maintained, or contains a cycle"
-        );
-        changed |= transform_changed;
-        if changed {
-            *global_transform = parent.mul_transform(*transform);
-        }
-        *global_transform
+) -> Result<(), MalformedHierarchy> {
+    // The parent is checked through a read-only query first, so a malformed hierarchy can never
+    // create a second mutable reference to the same `GlobalTransform`.
+    let child_parent = match parent_query.get(entity) {
+        Ok(child_parent) => child_parent.get(),
+        Err(_) => return Ok(()),
     };
+    if child_parent != expected_parent {
+        return Err(MalformedHierarchy {
+            entity,
+            expected_parent,
+            parent: child_parent,
+        });
+    }
+    // Entities without a `Transform` end the branch, like in Bevy's own propagation.
+    let (transform, transform_changed, mut global_transform) =
+        match transform_query.get_unchecked(entity) {
+            Ok(item) => item,
+            Err(_) => return Ok(()),
+        };
+    changed |= transform_changed;
+    if changed {
+        *global_transform = parent.mul_transform(*transform);
+    }
 
-    let (children, changed_children) = children_query.get(entity).map_err(drop)?;
+    let (children, changed_children) = match children_query.get(entity) {
+        Ok(item) => item,
+        Err(_) => return Ok(()),
+    };
     // If our `Children` has changed, we need to recalculate everything below us
     changed |= changed_children;
+    // Keep propagating to the siblings of a malformed branch, and report the first error.
+    let mut result = Ok(());
     for child in children {
-        let _ = propagate_recursive(
-            &global_matrix,
+        let child_result = propagate_recursive(
+            &global_transform,
             transform_query,
+            parent_query,
             children_query,
             *child,
             entity,
             changed,
         );
+        result = result.and(child_result);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Runs one update, `seconds` after the last one.
+    pub(crate) fn step_app(app: &mut App, seconds: f64) {
+        let mut time = app.world.resource_mut::<Time>();
+        let last = time.last_update().unwrap_or_else(Instant::now);
+        time.update_with_instant(last + Duration::from_secs_f64(seconds));
+        app.update();
+    }
+
+    /// The largest error allowed when converting between grid positions and `f64` offsets.
+    const TOLERANCE: f64 = 0.0005;
+
+    /// Moves from `cell` into the neighboring cell in `direction`, and checks that the offset
+    /// between the two positions survives the round trip through a grid cell and `f32` translation.
+    fn round_trip<P: GridPrecision>(
+        settings: &FloatingOriginSettings,
+        cell: GridCell<P>,
+        direction: DVec3,
+    ) -> GridCell<P> {
+        let l = settings.grid_edge_length();
+        let mut new_cell = cell;
+        for translation in [
+            DVec3::new(0.0001, -0.0004, 0.0007),
+            DVec3::new(0.123_456_7, -0.345_678_9, 0.499_9) * l,
+        ] {
+            let offset = direction * l + translation;
+            let translation;
+            (new_cell, translation) = settings
+                .checked_recenter(&cell, offset)
+                .unwrap_or_else(|| panic!("{offset} from {cell:?} is past the edge of the grid"));
+            let round_trip = settings.grid_offset_double(&cell, &new_cell) + translation.as_dvec3();
+            let error = (round_trip - offset).abs().max_element();
+            assert!(
+                error < TOLERANCE,
+                "{offset} from {cell:?} became {round_trip}, with an error of {error}"
+            );
+        }
+        new_cell
+    }
+
+    /// Checks precision in the outermost cells of the grid, and that moving past them is detected.
+    fn round_trip_at_extents<P: GridPrecision>() {
+        for settings in [
+            FloatingOriginSettings::default(),
+            FloatingOriginSettings::new(1.0, 0.01),
+            FloatingOriginSettings::new(1_000.1, 10.0),
+        ] {
+            let max = GridCell::new(P::MAX, P::MAX, P::MAX);
+            let min = GridCell::new(P::MIN, P::MIN, P::MIN);
+            assert_eq!(round_trip(&settings, max - GridCell::ONE, DVec3::ONE), max);
+            assert_eq!(round_trip(&settings, min + GridCell::ONE, -DVec3::ONE), min);
+
+            let edge = DVec3::splat(settings.grid_edge_length());
+            assert_eq!(settings.checked_recenter(&max, edge), None);
+            assert_eq!(settings.checked_recenter(&min, -edge), None);
+        }
+    }
+
+    #[test]
+    fn round_trip_i8() {
+        round_trip_at_extents::<i8>();
+    }
+
+    #[test]
+    fn round_trip_i16() {
+        round_trip_at_extents::<i16>();
+    }
+
+    #[test]
+    fn round_trip_i32() {
+        round_trip_at_extents::<i32>();
+    }
+
+    #[test]
+    fn round_trip_i64() {
+        round_trip_at_extents::<i64>();
+    }
+
+    #[test]
+    fn round_trip_i128() {
+        round_trip_at_extents::<i128>();
+    }
+
+    #[test]
+    fn round_trip_i256() {
+        round_trip_at_extents::<I256>();
+    }
+
+    #[test]
+    fn round_trip_unsigned() {
+        round_trip_at_extents::<u8>();
+        round_trip_at_extents::<u16>();
+        round_trip_at_extents::<u32>();
+        round_trip_at_extents::<u64>();
+        round_trip_at_extents::<u128>();
+    }
+
+    /// An edge length that isn't exactly representable as `f32` must not lose precision when
+    /// multiplied by a large cell index.
+    #[test]
+    fn grid_position_uses_f64_edge_length() {
+        let settings = FloatingOriginSettings::new(0.1, 0.001);
+        let cell = GridCell::<i64>::new(1_000_000, 0, -1_000_000);
+        let position = settings.global_pos_double(&cell, &Transform::from_xyz(0.0, 0.0004, 0.0));
+        let error = (position - DVec3::new(100_000.0, 0.0004, -100_000.0))
+            .abs()
+            .max_element();
+        assert!(error < TOLERANCE, "error of {error}");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "overflow")]
+    fn cell_overflow_panics_in_debug() {
+        let _ = GridCell::<i8>::new(i8::MAX, 0, 0) + GridCell::ONE;
+    }
+
+    /// A child with the wrong `Parent` is skipped, without stopping its siblings from updating.
+    #[test]
+    fn malformed_hierarchy_is_skipped() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let root = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::ZERO,
+                FloatingOrigin,
+            ))
+            .id();
+        let children = [(); 2].map(|_| {
+            let child = app
+                .world
+                .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                    1.0, 0.0, 0.0,
+                )))
+                .id();
+            app.world.entity_mut(root).push_children(&[child]);
+            child
+        });
+        app.update();
+
+        let parent = Parent::from_world(&mut app.world);
+        app.world.entity_mut(children[0]).insert(parent);
+        for child in children {
+            app.world.get_mut::<Transform>(child).unwrap().translation.x = 2.0;
+        }
+        app.update();
+        let x = |entity| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+                .x
+        };
+        assert_eq!(x(children[0]), 1.0);
+        assert_eq!(x(children[1]), 2.0);
+    }
+
+    /// Grid entities are placed correctly when Bevy's own propagation runs too.
+    #[test]
+    fn alongside_transform_plugin() {
+        let mut app = App::new();
+        app.add_plugin(TransformPlugin)
+            .add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let ship = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(1.0, 0.0, 0.0)),
+                GridCell::<i64>::new(1, 0, 0),
+            ))
+            .id();
+        let part = app
+            .world
+            .spawn(TransformBundle::from_transform(Transform::from_xyz(
+                0.0, 2.0, 0.0,
+            )))
+            .id();
+        app.world.entity_mut(ship).push_children(&[part]);
+        let translation = |app: &App, entity| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+        };
+
+        app.update();
+        assert_eq!(translation(&app, ship), Vec3::new(10_001.0, 0.0, 0.0));
+        assert_eq!(translation(&app, part), Vec3::new(10_001.0, 2.0, 0.0));
+
+        app.world.get_mut::<Transform>(ship).unwrap().translation.x = 3.0;
+        app.update();
+        assert_eq!(translation(&app, ship), Vec3::new(10_003.0, 0.0, 0.0));
+        assert_eq!(translation(&app, part), Vec3::new(10_003.0, 2.0, 0.0));
+    }
+
+    /// The plugins can be added in either order, and Bevy's propagation still runs first.
+    #[test]
+    fn before_transform_plugin() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default())
+            .add_plugin(TransformPlugin);
+        let entity = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::new(1, 0, 0)))
+            .id();
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let translation = |app: &App| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+        };
+
+        app.update();
+        assert_eq!(translation(&app), Vec3::new(10_000.0, 0.0, 0.0));
+        app.world
+            .get_mut::<Transform>(entity)
+            .unwrap()
+            .translation
+            .x = 1.0;
+        app.update();
+        assert_eq!(translation(&app), Vec3::new(10_001.0, 0.0, 0.0));
+
+        // Systems that aren't ordered run in any order, so check the ordering itself, which the
+        // stage resolves into dependencies on its first run.
+        let stage = app
+            .schedule
+            .get_stage::<SystemStage>(CoreStage::PostUpdate)
+            .unwrap();
+        let systems = stage.parallel_systems();
+        let index = |name: &str| {
+            systems
+                .iter()
+                .position(|system| system.name().starts_with(name))
+                .unwrap()
+        };
+        let propagate = index("bevy_transform::systems::transform_propagate_system");
+        let recenter = index("big_space::recenter_transform_on_grid");
+        assert!(systems[recenter].dependencies().contains(&propagate));
+    }
+
+    /// Changing the render layers of an entity moves it to the matching origin.
+    #[test]
+    fn render_layers_change() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::new(1, 0, 0),
+            FloatingOrigin,
+            RenderLayers::layer(1),
+        ));
+        let entity = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::ZERO))
+            .id();
+        let x = |app: &App| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+                .x
+        };
+
+        app.update();
+        assert_eq!(x(&app), 0.0);
+        app.world.entity_mut(entity).insert(RenderLayers::layer(1));
+        app.update();
+        assert_eq!(x(&app), -10_000.0);
+        app.world.entity_mut(entity).remove::<RenderLayers>();
+        app.update();
+        assert_eq!(x(&app), 0.0);
+    }
+
+    /// Entities that share render layers with several origins use the one with the highest
+    /// priority, no matter which was spawned first.
+    #[test]
+    fn origin_priority() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let second = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i64>::new(1, 0, 0),
+                FloatingOrigin,
+                OriginPriority(1),
+            ))
+            .id();
+        let entity = app
+            .world
+            .spawn((TransformBundle::default(), GridCell::<i64>::ZERO))
+            .id();
+        let x = |app: &App| {
+            app.world
+                .get::<GlobalTransform>(entity)
+                .unwrap()
+                .translation()
+                .x
+        };
+
+        app.update();
+        assert_eq!(x(&app), -10_000.0);
+        app.world.entity_mut(second).insert(OriginPriority(-1));
+        app.update();
+        assert_eq!(x(&app), 0.0);
+    }
+
+    /// Movement past the edge of the grid is discarded with the default clamping policy, even when
+    /// the cell stays the same.
+    #[test]
+    fn fixed_translation_clamps_at_edge() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i8>::default());
+        let edge = 10_000 * FixedTranslation::ONE_UNIT;
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i8>::new(i8::MAX, 0, 0),
+                FixedTranslation::new(3 * edge + 1, 0, 0),
+            ))
+            .id();
+
+        app.update();
+        let entity = app.world.entity(entity);
+        assert_eq!(
+            entity.get::<GridCell<i8>>(),
+            Some(&GridCell::new(i8::MAX, 0, 0))
+        );
+        assert_eq!(
+            entity.get::<FixedTranslation>(),
+            Some(&FixedTranslation::new(1, 0, 0))
+        );
+        assert!(entity.get::<Transform>().unwrap().translation.x < 1.0);
     }
-    Ok(())
 }
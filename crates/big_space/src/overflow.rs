@@ -0,0 +1,40 @@
+//! Detecting entities that move past the edge of the grid.
+//!
+//! The number of grid cells is limited by the [`GridPrecision`] in use. An `i8` or `i16` grid is
+//! small enough that entities can realistically travel past its edge, so this is detected and
+//! handled according to the [`GridOverflowPolicy`] in
+//! [`FloatingOriginSettings`](crate::FloatingOriginSettings), and reported with a [`GridOverflow`]
+//! event.
+
+use bevy::prelude::*;
+
+use crate::{precision::GridPrecision, GridCell};
+
+/// What to do with an entity that moved past the edge of the grid.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GridOverflowPolicy {
+    /// Keep the entity in the outermost cell, discarding any movement past the edge of the grid.
+    #[default]
+    Clamp,
+    /// Despawn the entity, and all of its descendants.
+    Despawn,
+    /// Leave the entity where it is. Its [`Transform`] will keep growing past the edge of the grid
+    /// until it is moved back, losing precision.
+    EmitEvent,
+    /// Panic.
+    Panic,
+}
+
+/// Sent when an entity moves past the edge of the grid. This is sent for every
+/// [`GridOverflowPolicy`], after the policy has been applied, except for
+/// [`GridOverflowPolicy::Panic`].
+#[derive(Debug, Clone, Copy)]
+pub struct GridOverflow<P: GridPrecision> {
+    /// The entity that overflowed the grid.
+    pub entity: Entity,
+    /// The cell the entity was in before it overflowed.
+    pub cell: GridCell<P>,
+    /// The translation of the entity, relative to `cell`, that overflowed the grid.
+    pub translation: Vec3,
+}
@@ -1,6 +1,10 @@
-use std::{hash::Hash, ops::Add};
+use std::{
+    fmt,
+    hash::Hash,
+    ops::{Add, Neg, Sub},
+};
 
-use bevy::reflect::Reflect;
+use bevy::reflect::{GetTypeRegistration, Reflect};
 
 pub trait GridPrecision:
     Default
@@ -14,6 +18,7 @@ pub trait GridPrecision:
     + Send
     + Sync
     + Reflect
+    + GetTypeRegistration
     + Add
     + std::fmt::Debug
     + std::fmt::Display
@@ -21,118 +26,515 @@ pub trait GridPrecision:
 {
     const ZERO: Self;
     const ONE: Self;
+    const MIN: Self;
+    const MAX: Self;
     fn wrapping_add(self, rhs: Self) -> Self;
     fn wrapping_sub(self, rhs: Self) -> Self;
+    /// Returns `None` if the result overflows.
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    /// Returns `None` if the result overflows.
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+    /// Clamps the result to [`Self::MIN`] and [`Self::MAX`] instead of overflowing.
+    fn saturating_add(self, rhs: Self) -> Self;
     fn as_f64(self) -> f64;
+    /// Converts from `f64`, saturating at the bounds of the type.
     fn from_f64(input: f64) -> Self;
+    /// Converts from `f64`, returning `None` if the input is out of the bounds of the type, or NaN.
+    fn checked_from_f64(input: f64) -> Option<Self>;
+    /// Returns `self - rhs` as an `f64`.
+    ///
+    /// The difference is computed exactly, and only rounded when it is converted to floating
+    /// point, so the result is as precise as possible even if `self` and `rhs` are too large to be
+    /// represented exactly by an `f64`, or the difference is out of the bounds of the type.
+    fn sub_f64(self, rhs: Self) -> f64;
+    /// Converts to an [`I256`], which can represent every value of every grid precision exactly.
+    fn as_i256(self) -> I256;
+    /// Converts from an [`I256`], returning `None` if the input is out of the bounds of the type.
+    fn checked_from_i256(input: I256) -> Option<Self>;
+
+    /// Adds the whole number `delta`, which may be negative even if the type is unsigned. Returns
+    /// `None` if the result is out of the bounds of the type, or `delta` is not finite.
+    fn checked_add_f64(self, delta: f64) -> Option<Self> {
+        // The sum is computed as an `I256`, so `delta` may be out of the bounds of the type as long
+        // as the result is not.
+        let sum = self.as_i256().checked_add(I256::checked_from_f64(delta)?)?;
+        Self::checked_from_i256(sum)
+    }
+
+    /// Like [`Self::checked_add_f64`], but clamps the result to [`Self::MIN`] and [`Self::MAX`]
+    /// instead of overflowing.
+    fn saturating_add_f64(self, delta: f64) -> Self {
+        self.checked_add_f64(delta)
+            .unwrap_or(if delta < 0.0 { Self::MIN } else { Self::MAX })
+    }
 }
 
-impl GridPrecision for i8 {
-    const ZERO: Self = 0;
-    const ONE: Self = 1;
+macro_rules! impl_grid_precision {
+    ($($t:ty: $upper_bound:expr),* $(,)?) => {$(
+        impl GridPrecision for $t {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+            const MIN: Self = Self::MIN;
+            const MAX: Self = Self::MAX;
 
-    #[inline]
-    fn wrapping_add(self, rhs: Self) -> Self {
-        Self::wrapping_add(self, rhs)
+            #[inline]
+            fn wrapping_add(self, rhs: Self) -> Self {
+                Self::wrapping_add(self, rhs)
+            }
+            #[inline]
+            fn wrapping_sub(self, rhs: Self) -> Self {
+                Self::wrapping_sub(self, rhs)
+            }
+            #[inline]
+            fn checked_add(self, rhs: Self) -> Option<Self> {
+                Self::checked_add(self, rhs)
+            }
+            #[inline]
+            fn checked_sub(self, rhs: Self) -> Option<Self> {
+                Self::checked_sub(self, rhs)
+            }
+            #[inline]
+            fn saturating_add(self, rhs: Self) -> Self {
+                Self::saturating_add(self, rhs)
+            }
+            #[inline]
+            fn as_f64(self) -> f64 {
+                self as f64
+            }
+            #[inline]
+            fn from_f64(input: f64) -> Self {
+                input as Self
+            }
+            #[inline]
+            fn checked_from_f64(input: f64) -> Option<Self> {
+                (input >= Self::MIN as f64 && input < $upper_bound).then_some(input as Self)
+            }
+            #[inline]
+            fn sub_f64(self, rhs: Self) -> f64 {
+                let difference = self.abs_diff(rhs) as f64;
+                if self < rhs {
+                    -difference
+                } else {
+                    difference
+                }
+            }
+            #[inline]
+            fn as_i256(self) -> I256 {
+                I256::from(self)
+            }
+            fn checked_from_i256(input: I256) -> Option<Self> {
+                // Every primitive fits in the low 128 bits, with the high bits only extending the
+                // sign.
+                match input.to_parts() {
+                    (0, lo) => Self::try_from(lo).ok(),
+                    (-1, lo) if (lo as i128) < 0 => Self::try_from(lo as i128).ok(),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+// The upper bound is one past `MAX`. For signed types, this is the negation of `MIN`. Both are
+// powers of two, so they are exactly representable as `f64`.
+impl_grid_precision!(
+    i8: -(Self::MIN as f64),
+    i16: -(Self::MIN as f64),
+    i32: -(Self::MIN as f64),
+    i64: -(Self::MIN as f64),
+    i128: -(Self::MIN as f64),
+    u8: 2.0 * ((Self::MAX / 2 + 1) as f64),
+    u16: 2.0 * ((Self::MAX / 2 + 1) as f64),
+    u32: 2.0 * ((Self::MAX / 2 + 1) as f64),
+    u64: 2.0 * ((Self::MAX / 2 + 1) as f64),
+    u128: 2.0 * ((Self::MAX / 2 + 1) as f64),
+);
+
+const TWO_POW_128: f64 = (1u128 << 127) as f64 * 2.0;
+const TWO_POW_255: f64 = (1u128 << 127) as f64 * TWO_POW_128;
+
+/// A 256-bit signed integer, for grids larger than [`i128`] can index.
+///
+/// With a grid cell edge length of 10,000 meters, this covers a cube roughly 1.2e+65 light years
+/// on each edge. Arithmetic is two's complement, the same as the primitive integer types, and the
+/// [`Add`] and [`Sub`] operators panic on overflow.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct I256 {
+    // The field order matters: deriving `Ord` compares the signed high bits first.
+    hi: i128,
+    lo: u128,
+}
+
+impl I256 {
+    pub const ZERO: Self = Self::from_parts(0, 0);
+    pub const ONE: Self = Self::from_parts(0, 1);
+    pub const MIN: Self = Self::from_parts(i128::MIN, 0);
+    pub const MAX: Self = Self::from_parts(i128::MAX, u128::MAX);
+
+    /// Creates an integer from its high and low 128 bits.
+    pub const fn from_parts(hi: i128, lo: u128) -> Self {
+        Self { hi, lo }
     }
-    #[inline]
-    fn wrapping_sub(self, rhs: Self) -> Self {
-        Self::wrapping_sub(self, rhs)
+
+    /// The high and low 128 bits of this integer.
+    pub const fn to_parts(self) -> (i128, u128) {
+        (self.hi, self.lo)
     }
-    #[inline]
-    fn as_f64(self) -> f64 {
-        self as f64
+
+    pub const fn is_negative(self) -> bool {
+        self.hi < 0
     }
-    #[inline]
-    fn from_f64(input: f64) -> Self {
-        input as Self
+
+    pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        let (lo, carry) = self.lo.overflowing_add(rhs.lo);
+        let (hi, overflow_a) = self.hi.overflowing_add(rhs.hi);
+        let (hi, overflow_b) = hi.overflowing_add(carry as i128);
+        // Adding the carry can only overflow back into range if adding the high bits overflowed.
+        (Self { hi, lo }, overflow_a != overflow_b)
+    }
+
+    pub fn overflowing_sub(self, rhs: Self) -> (Self, bool) {
+        let (lo, borrow) = self.lo.overflowing_sub(rhs.lo);
+        let (hi, overflow_a) = self.hi.overflowing_sub(rhs.hi);
+        let (hi, overflow_b) = hi.overflowing_sub(borrow as i128);
+        (Self { hi, lo }, overflow_a != overflow_b)
+    }
+
+    pub fn wrapping_neg(self) -> Self {
+        Self::ZERO.overflowing_sub(self).0
+    }
+
+    /// Multiplies by `rhs`, returning `None` if the result overflows.
+    pub fn checked_mul_u64(self, rhs: u64) -> Option<Self> {
+        let mut limbs = self.unsigned_abs().to_limbs();
+        let mut carry = 0;
+        for limb in limbs.iter_mut().rev() {
+            let product = *limb as u128 * rhs as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        let magnitude = Self::from_limbs(limbs);
+        match (carry, self.is_negative()) {
+            (0, false) if !magnitude.is_negative() => Some(magnitude),
+            // `MIN` has the largest magnitude, and is its own negation.
+            (0, true) if !magnitude.is_negative() || magnitude == Self::MIN => {
+                Some(magnitude.wrapping_neg())
+            }
+            _ => None,
+        }
+    }
+
+    /// Divides by `rhs`, rounding towards zero, and returns the quotient and remainder. The
+    /// remainder has the same sign as `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rhs` is zero.
+    pub fn div_rem_u64(self, rhs: u64) -> (Self, i128) {
+        assert_ne!(rhs, 0, "attempt to divide by zero");
+        let mut limbs = self.unsigned_abs().to_limbs();
+        let remainder = div_rem_limbs(&mut limbs, rhs as u128) as i128;
+        // The quotient is never larger than the dividend, so it can't overflow when negated.
+        let quotient = Self::from_limbs(limbs);
+        if self.is_negative() {
+            (quotient.wrapping_neg(), -remainder)
+        } else {
+            (quotient, remainder)
+        }
+    }
+
+    /// The magnitude of `self`. This is correct for `MIN` too, as long as the result is treated as
+    /// unsigned.
+    fn unsigned_abs(self) -> Self {
+        if self.is_negative() {
+            self.wrapping_neg()
+        } else {
+            self
+        }
+    }
+
+    /// The bits of `self` as four 64 bit limbs, most significant first.
+    fn to_limbs(self) -> [u64; 4] {
+        let hi = self.hi as u128;
+        [
+            (hi >> 64) as u64,
+            hi as u64,
+            (self.lo >> 64) as u64,
+            self.lo as u64,
+        ]
+    }
+
+    fn from_limbs(limbs: [u64; 4]) -> Self {
+        let hi = (limbs[0] as u128) << 64 | limbs[1] as u128;
+        let lo = (limbs[2] as u128) << 64 | limbs[3] as u128;
+        Self::from_parts(hi as i128, lo)
+    }
+
+    /// Converts to `f64`, treating the bits of `self` as an unsigned integer.
+    fn as_unsigned_f64(self) -> f64 {
+        self.hi as u128 as f64 * TWO_POW_128 + self.lo as f64
     }
 }
 
-impl GridPrecision for i16 {
-    const ZERO: Self = 0;
-    const ONE: Self = 1;
+impl GridPrecision for I256 {
+    const ZERO: Self = Self::ZERO;
+    const ONE: Self = Self::ONE;
+    const MIN: Self = Self::MIN;
+    const MAX: Self = Self::MAX;
 
     #[inline]
     fn wrapping_add(self, rhs: Self) -> Self {
-        Self::wrapping_add(self, rhs)
+        self.overflowing_add(rhs).0
     }
     #[inline]
     fn wrapping_sub(self, rhs: Self) -> Self {
-        Self::wrapping_sub(self, rhs)
+        self.overflowing_sub(rhs).0
     }
     #[inline]
-    fn as_f64(self) -> f64 {
-        self as f64
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        let (result, overflow) = self.overflowing_add(rhs);
+        (!overflow).then_some(result)
     }
     #[inline]
-    fn from_f64(input: f64) -> Self {
-        input as Self
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        let (result, overflow) = self.overflowing_sub(rhs);
+        (!overflow).then_some(result)
     }
-}
-
-impl GridPrecision for i32 {
-    const ZERO: Self = 0;
-    const ONE: Self = 1;
-
     #[inline]
-    fn wrapping_add(self, rhs: Self) -> Self {
-        Self::wrapping_add(self, rhs)
+    fn saturating_add(self, rhs: Self) -> Self {
+        match self.overflowing_add(rhs) {
+            (result, false) => result,
+            (_, true) if rhs.is_negative() => Self::MIN,
+            (_, true) => Self::MAX,
+        }
     }
     #[inline]
-    fn wrapping_sub(self, rhs: Self) -> Self {
-        Self::wrapping_sub(self, rhs)
+    fn as_f64(self) -> f64 {
+        if self.is_negative() {
+            // `MIN` wraps to itself, but is still correct when treated as unsigned.
+            -self.wrapping_neg().as_unsigned_f64()
+        } else {
+            self.as_unsigned_f64()
+        }
+    }
+    fn from_f64(input: f64) -> Self {
+        if input.is_nan() {
+            Self::ZERO
+        } else if input >= TWO_POW_255 {
+            Self::MAX
+        } else if input <= -TWO_POW_255 {
+            Self::MIN
+        } else {
+            // Both halves are exact: the high bits are a power of two multiple of the input, and
+            // the remainder has no more significant bits than the input.
+            let magnitude = input.abs().trunc();
+            let hi = (magnitude / TWO_POW_128).floor();
+            let lo = magnitude - hi * TWO_POW_128;
+            let result = Self::from_parts(hi as i128, lo as u128);
+            if input < 0.0 {
+                result.wrapping_neg()
+            } else {
+                result
+            }
+        }
     }
     #[inline]
-    fn as_f64(self) -> f64 {
-        self as f64
+    fn checked_from_f64(input: f64) -> Option<Self> {
+        (-TWO_POW_255..TWO_POW_255)
+            .contains(&input)
+            .then(|| Self::from_f64(input))
     }
     #[inline]
-    fn from_f64(input: f64) -> Self {
-        input as Self
+    fn as_i256(self) -> I256 {
+        self
+    }
+    #[inline]
+    fn checked_from_i256(input: I256) -> Option<Self> {
+        Some(input)
+    }
+    fn sub_f64(self, rhs: Self) -> f64 {
+        // The difference between the larger and smaller value is always in `0..2^256`, so it is
+        // exact when treated as unsigned, even if it overflows.
+        if self < rhs {
+            -rhs.wrapping_sub(self).as_unsigned_f64()
+        } else {
+            self.wrapping_sub(rhs).as_unsigned_f64()
+        }
+    }
+}
+
+/// Divides the unsigned integer in `limbs` by `divisor` in place, and returns the remainder.
+/// `divisor` must be no larger than `u64::MAX`.
+fn div_rem_limbs(limbs: &mut [u64; 4], divisor: u128) -> u128 {
+    let mut remainder = 0;
+    for limb in limbs.iter_mut() {
+        let current = (remainder << 64) | *limb as u128;
+        *limb = (current / divisor) as u64;
+        remainder = current % divisor;
     }
+    remainder
 }
 
-impl GridPrecision for i64 {
-    const ZERO: Self = 0;
-    const ONE: Self = 1;
+macro_rules! impl_from_signed {
+    ($($t:ty),*) => {$(
+        impl From<$t> for I256 {
+            fn from(value: $t) -> Self {
+                Self::from_parts(if value < 0 { -1 } else { 0 }, value as i128 as u128)
+            }
+        }
+    )*};
+}
 
-    #[inline]
-    fn wrapping_add(self, rhs: Self) -> Self {
-        Self::wrapping_add(self, rhs)
+macro_rules! impl_from_unsigned {
+    ($($t:ty),*) => {$(
+        impl From<$t> for I256 {
+            fn from(value: $t) -> Self {
+                Self::from_parts(0, value as u128)
+            }
+        }
+    )*};
+}
+
+impl_from_signed!(i8, i16, i32, i64, i128);
+impl_from_unsigned!(u8, u16, u32, u64, u128);
+
+impl Add for I256 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("attempt to add with overflow")
     }
-    #[inline]
-    fn wrapping_sub(self, rhs: Self) -> Self {
-        Self::wrapping_sub(self, rhs)
+}
+
+impl Sub for I256 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("attempt to subtract with overflow")
     }
-    #[inline]
-    fn as_f64(self) -> f64 {
-        self as f64
+}
+
+impl Neg for I256 {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        assert_ne!(self, Self::MIN, "attempt to negate with overflow");
+        self.wrapping_neg()
     }
-    #[inline]
-    fn from_f64(input: f64) -> Self {
-        input as Self
+}
+
+impl fmt::Display for I256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The magnitude of `MIN` is still correct when treated as unsigned.
+        let magnitude = self.unsigned_abs();
+        let mut limbs = magnitude.to_limbs();
+
+        // Split the magnitude into base 10^19 digits, the largest power of ten that fits in a u64.
+        let mut digits = Vec::new();
+        loop {
+            digits.push(div_rem_limbs(&mut limbs, 10_000_000_000_000_000_000));
+            if limbs == [0; 4] {
+                break;
+            }
+        }
+
+        let mut output = digits.pop().unwrap_or_default().to_string();
+        for digit in digits.iter().rev() {
+            output.push_str(&format!("{digit:019}"));
+        }
+        f.pad_integral(!self.is_negative(), "", &output)
+    }
+}
+
+impl fmt::Debug for I256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
     }
 }
 
-impl GridPrecision for i128 {
-    const ZERO: Self = 0;
-    const ONE: Self = 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[inline]
-    fn wrapping_add(self, rhs: Self) -> Self {
-        Self::wrapping_add(self, rhs)
+    #[test]
+    fn i256_arithmetic() {
+        let big = I256::from(i128::MAX);
+        assert_eq!(big + I256::ONE, I256::from_parts(0, 1 << 127));
+        assert_eq!(big + I256::ONE - I256::ONE, big);
+        assert_eq!(-I256::ONE, I256::from(-1));
+        assert_eq!(I256::from(-5) + I256::from(3), I256::from(-2));
+        assert!(I256::MIN < I256::from(i128::MIN) && I256::from(-1) < I256::ZERO);
+
+        assert_eq!(I256::MAX.checked_add(I256::ONE), None);
+        assert_eq!(I256::MIN.checked_sub(I256::ONE), None);
+        assert_eq!(I256::MIN.checked_add(I256::MAX), Some(I256::from(-1)));
+        assert_eq!(I256::MAX.saturating_add(I256::MAX), I256::MAX);
+        assert_eq!(I256::MIN.saturating_add(I256::MIN), I256::MIN);
+        assert_eq!(I256::MAX.wrapping_add(I256::ONE), I256::MIN);
     }
-    #[inline]
-    fn wrapping_sub(self, rhs: Self) -> Self {
-        Self::wrapping_sub(self, rhs)
+
+    #[test]
+    fn i256_f64_conversion() {
+        for value in [0.0, 1.0, -1.0, 12_345.0, -3e40, 2e76, -5.7e76] {
+            assert_eq!(I256::from_f64(value).as_f64(), value);
+            assert_eq!(I256::checked_from_f64(value), Some(I256::from_f64(value)));
+        }
+        assert_eq!(I256::from_f64(-3.0), I256::from(-3));
+        assert_eq!(I256::from_f64(-2.9), I256::from(-2));
+        assert_eq!(I256::from_f64(1e300), I256::MAX);
+        assert_eq!(I256::from_f64(-1e300), I256::MIN);
+        assert_eq!(I256::from_f64(f64::NAN), I256::ZERO);
+        assert_eq!(I256::checked_from_f64(1e300), None);
+        assert_eq!(I256::checked_from_f64(f64::NAN), None);
+        assert_eq!(I256::MIN.as_f64(), -TWO_POW_255);
     }
-    #[inline]
-    fn as_f64(self) -> f64 {
-        self as f64
+
+    #[test]
+    fn exact_differences() {
+        assert_eq!(I256::MAX.sub_f64(I256::MAX - I256::ONE), 1.0);
+        assert_eq!(I256::MIN.sub_f64(I256::MIN + I256::from(7)), -7.0);
+        assert_eq!(I256::MAX.sub_f64(I256::MIN), 2.0 * TWO_POW_255);
+        assert_eq!(i128::MAX.sub_f64(i128::MAX - 3), 3.0);
+        assert_eq!(i8::MIN.sub_f64(i8::MAX), -255.0);
+        assert_eq!(0u8.sub_f64(u8::MAX), -255.0);
+        assert_eq!(3u64.checked_add_f64(-2.0), Some(1));
+        assert_eq!(3u64.checked_add_f64(-4.0), None);
     }
-    #[inline]
-    fn from_f64(input: f64) -> Self {
-        input as Self
+
+    /// The sum only has to fit in the type, not the delta.
+    #[test]
+    fn add_f64() {
+        assert_eq!((-100i8).checked_add_f64(200.0), Some(100));
+        assert_eq!(200u8.checked_add_f64(-300.0), None);
+        assert_eq!(200u8.checked_add_f64(-200.0), Some(0));
+        assert_eq!(i8::MIN.checked_add_f64(255.0), Some(i8::MAX));
+        assert_eq!(i8::MIN.checked_add_f64(256.0), None);
+        assert_eq!(
+            i64::MIN.checked_add_f64(2f64.powi(64) - 2048.0),
+            Some(i64::MAX - 2047)
+        );
+        assert_eq!(0i8.checked_add_f64(f64::INFINITY), None);
+        assert_eq!(0i8.checked_add_f64(f64::NAN), None);
+        assert_eq!(I256::MIN.checked_add_f64(-1.0), None);
+        assert_eq!((-100i8).saturating_add_f64(200.0), 100);
+        assert_eq!(100i8.saturating_add_f64(-300.0), i8::MIN);
+    }
+
+    #[test]
+    fn i256_display() {
+        assert_eq!(I256::ZERO.to_string(), "0");
+        assert_eq!(I256::from(-42).to_string(), "-42");
+        assert_eq!(I256::from(i128::MIN).to_string(), i128::MIN.to_string());
+        assert_eq!(
+            I256::MAX.to_string(),
+            "57896044618658097711785492504343953926634992332820282019728792003956564819967"
+        );
+        assert_eq!(
+            I256::MIN.to_string(),
+            "-57896044618658097711785492504343953926634992332820282019728792003956564819968"
+        );
+        assert_eq!(format!("{:>5}", I256::from(7)), "    7");
     }
 }
@@ -0,0 +1,185 @@
+//! Precise ray casting against grid entities.
+//!
+//! A [`GridRay`] starts at a position in the grid, and is tested against grid entities with `f64`
+//! math relative to its origin, so picking or line of sight checks against objects millions of
+//! kilometers away are as precise as against objects next to the ray's origin.
+
+use bevy::{math::DVec3, prelude::*, render::primitives::Aabb};
+
+use crate::{precision::GridPrecision, FloatingOriginSettings, GridCell};
+
+/// A ray starting at `translation` within `cell`, pointing along `direction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridRay<P: GridPrecision> {
+    /// The cell the ray starts in.
+    pub cell: GridCell<P>,
+    /// The start of the ray, relative to the center of `cell`.
+    pub translation: Vec3,
+    direction: DVec3,
+}
+
+impl<P: GridPrecision> GridRay<P> {
+    /// Creates a ray starting at (`cell`, `translation`). Returns `None` if `direction` is zero, or
+    /// not finite.
+    pub fn new(cell: GridCell<P>, translation: Vec3, direction: DVec3) -> Option<Self> {
+        let direction = direction.try_normalize()?;
+        Some(Self {
+            cell,
+            translation,
+            direction,
+        })
+    }
+
+    /// Creates a grid ray from a ray relative to the center of `cell`, like one from
+    /// [`Camera::viewport_to_world`], with `cell` being the cell of the
+    /// [`FloatingOrigin`](crate::FloatingOrigin) in the default [`OriginMode`](crate::OriginMode).
+    pub fn from_ray(cell: GridCell<P>, ray: Ray) -> Option<Self> {
+        Self::new(cell, ray.origin, ray.direction.as_dvec3())
+    }
+
+    /// The normalized direction of the ray.
+    pub fn direction(&self) -> DVec3 {
+        self.direction
+    }
+
+    /// Returns the offset from the start of the ray to the center of the grid entity at (`cell`,
+    /// `translation`).
+    pub fn offset_to(
+        &self,
+        settings: &FloatingOriginSettings,
+        cell: &GridCell<P>,
+        translation: Vec3,
+    ) -> DVec3 {
+        settings.grid_offset_double(&self.cell, cell) + translation.as_dvec3()
+            - self.translation.as_dvec3()
+    }
+
+    /// Returns the point `distance` along the ray, relative to the start of the ray.
+    pub fn offset_at(&self, distance: f64) -> DVec3 {
+        self.direction * distance
+    }
+
+    /// Returns the distance along the ray to the first point inside a sphere with `radius`,
+    /// centered at the translation of the grid entity at (`cell`, `transform`), or `None` if the
+    /// ray misses it. The distance is zero if the ray starts inside the sphere.
+    pub fn intersect_sphere(
+        &self,
+        settings: &FloatingOriginSettings,
+        cell: &GridCell<P>,
+        transform: &Transform,
+        radius: f64,
+    ) -> Option<f64> {
+        let center = self.offset_to(settings, cell, transform.translation);
+        let closest = center.dot(self.direction);
+        // Measuring the distance from the center to the ray directly, instead of subtracting
+        // squared lengths, keeps precision when the sphere is small and far away.
+        let miss_squared = (center - self.direction * closest).length_squared();
+        let half_chord_squared = radius * radius - miss_squared;
+        if half_chord_squared < 0.0 {
+            return None;
+        }
+        let half_chord = half_chord_squared.sqrt();
+        (closest + half_chord >= 0.0).then(|| (closest - half_chord).max(0.0))
+    }
+
+    /// Returns the distance along the ray to the first point inside `aabb`, in the local space of
+    /// the grid entity at (`cell`, `transform`), or `None` if the ray misses it. The box is rotated
+    /// and scaled with the entity. The distance is zero if the ray starts inside the box.
+    pub fn intersect_aabb(
+        &self,
+        settings: &FloatingOriginSettings,
+        cell: &GridCell<P>,
+        transform: &Transform,
+        aabb: &Aabb,
+    ) -> Option<f64> {
+        // Move the ray into the local space of the entity, where the box is axis aligned. Distances
+        // along the local direction stay the same as along the ray.
+        let inverse_rotation = transform.rotation.as_f64().inverse();
+        let scale = transform.scale.as_dvec3();
+        let origin = inverse_rotation * -self.offset_to(settings, cell, transform.translation)
+            / scale
+            - Vec3::from(aabb.center).as_dvec3();
+        let direction = inverse_rotation * self.direction / scale;
+        let half_extents = Vec3::from(aabb.half_extents).as_dvec3();
+
+        let near = (-half_extents - origin) / direction;
+        let far = (half_extents - origin) / direction;
+        let entry = near.min(far).max_element().max(0.0);
+        let exit = near.max(far).min_element();
+        (entry <= exit).then_some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ray cast from the far edge of an `i64` grid, toward a one meter sphere 10^12 meters away.
+    #[test]
+    fn sphere_far_away() {
+        let settings = FloatingOriginSettings::new(10_000.0, 100.0);
+        let ray = GridRay::new(
+            GridCell::<i64>::new(i64::MAX, 0, 0),
+            Vec3::new(0.0, 0.25, 0.0),
+            DVec3::NEG_X,
+        )
+        .unwrap();
+        let cell = GridCell::new(i64::MAX - 100_000_000, 0, 0);
+
+        let hit = Transform::from_xyz(1.5, 0.0, 0.0);
+        let distance = ray.intersect_sphere(&settings, &cell, &hit, 1.0).unwrap();
+        let expected = 1e12 - 1.5 - 0.9375f64.sqrt();
+        assert!(
+            (distance - expected).abs() < 1e-3,
+            "{distance} != {expected}"
+        );
+
+        let miss = Transform::from_xyz(0.0, 1.3, 0.0);
+        assert_eq!(ray.intersect_sphere(&settings, &cell, &miss, 1.0), None);
+
+        let behind = GridCell::new(i64::MAX, 0, 0);
+        let behind =
+            ray.intersect_sphere(&settings, &behind, &Transform::from_xyz(5.0, 0.0, 0.0), 1.0);
+        assert_eq!(behind, None);
+    }
+
+    #[test]
+    fn aabb() {
+        let settings = FloatingOriginSettings::new(10_000.0, 100.0);
+        let ray = GridRay::new(GridCell::<i128>::ZERO, Vec3::ZERO, DVec3::X).unwrap();
+        let cell = GridCell::new(100_000_000, 0, 0);
+        let aabb = Aabb::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+
+        // Rotated 45 degrees, the corner of the box points at the ray.
+        let transform = Transform::from_xyz(-5.0, 1.2, 0.0)
+            .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4));
+        let distance = ray
+            .intersect_aabb(&settings, &cell, &transform, &aabb)
+            .unwrap();
+        let expected = 1e12 - 5.0 - (2f64.sqrt() - 1.2);
+        assert!(
+            (distance - expected).abs() < 1e-3,
+            "{distance} != {expected}"
+        );
+
+        // Not rotated, the box is too far from the ray.
+        let transform = Transform::from_xyz(-5.0, 1.2, 0.0);
+        assert_eq!(
+            ray.intersect_aabb(&settings, &cell, &transform, &aabb),
+            None
+        );
+
+        // Scaled up, it reaches the ray again.
+        let transform = transform.with_scale(Vec3::splat(2.0));
+        let distance = ray
+            .intersect_aabb(&settings, &cell, &transform, &aabb)
+            .unwrap();
+        assert!((distance - (1e12 - 7.0)).abs() < 1e-3);
+
+        let inside = Transform::from_xyz(0.5, 0.0, 0.0);
+        assert_eq!(
+            ray.intersect_aabb(&settings, &GridCell::ZERO, &inside, &aabb),
+            Some(0.0)
+        );
+    }
+}
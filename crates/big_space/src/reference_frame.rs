@@ -0,0 +1,633 @@
+//! Nested reference frames.
+//!
+//! Any entity with a [`GridCell`] can act as a reference frame for its children. If a child entity
+//! also has a [`GridCell`], that cell and its [`Transform`] are relative to the position of the
+//! parent, rather than the root grid. The child's grid is centered on the parent, so cell
+//! `(0, 0, 0)` of a planet-local grid always follows the planet, no matter how far it moves.
+//!
+//! By default, the axes of a nested frame stay aligned with the frame it is inside of, and only the
+//! position of the parent is followed. Adding a [`RotatingFrame`] to the parent makes the axes of
+//! its grid rotate with it too, so entities standing on a spinning planet keep stable local
+//! coordinates while the planet rotates.
+//!
+//! Frames can be nested to any depth, e.g. a ship interior inside a ship orbiting a moon orbiting a
+//! planet. Positions are only ever combined in `f64`, relative to the nearest frame shared with the
+//! [`FloatingOrigin`], so entities near the origin stay precise regardless of where the frames
+//! themselves are in the universe.
+//!
+//! A grid entity is only inside a frame if its parent is also a grid entity. A grid entity with any
+//! other parent is placed in the root grid, and the transform of its parent is ignored. This is
+//! logged as a warning by [`update_reference_frames`] when the parent is set.
+
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+    render::view::RenderLayers,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    events::FloatingOriginShifted, precision::GridPrecision, FloatingOrigin,
+    FloatingOriginSettings, GridCell, OriginMode, OriginPriority,
+};
+
+/// Makes the grid of the reference frame defined by this entity rotate with the entity's
+/// [`Transform`].
+///
+/// Without this component, the grid children of this entity follow its position, but not its
+/// rotation.
+#[derive(Component, Default, Debug, Clone, Copy, Reflect)]
+#[reflect(Component, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RotatingFrame;
+
+/// A reference frame that contains grid entities, as well as the position of the frame relative to
+/// the frame it is itself inside of.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<P: GridPrecision> {
+    /// The frame this frame is inside of, or `None` if it is in the root grid.
+    pub parent: Option<Entity>,
+    /// The cell of this frame's entity, in the parent frame.
+    pub cell: GridCell<P>,
+    /// The translation of this frame's entity, relative to its cell.
+    pub translation: DVec3,
+    /// The rotation of this frame's axes relative to the axes of the parent frame. This is the
+    /// identity unless the frame's entity has a [`RotatingFrame`].
+    pub rotation: DQuat,
+    /// Whether the position of this frame changed this update.
+    pub changed: bool,
+}
+
+/// The position of a [`FloatingOrigin`] in every frame between it and the root grid. Depending on
+/// the [`OriginMode`], this is either the position of the center of the origin's cell, or of the
+/// origin itself.
+#[derive(Debug, Clone)]
+pub struct OriginFrames<P: GridPrecision> {
+    entity: Entity,
+    cell: GridCell<P>,
+    frame: Option<Entity>,
+    layers: RenderLayers,
+    priority: OriginPriority,
+    /// The position of the origin's cell in each frame from the origin's frame up to the root. The
+    /// root grid is keyed as `None`.
+    cells: FramePositions<P>,
+    changed: bool,
+}
+
+impl<P: GridPrecision> OriginFrames<P> {
+    /// The [`FloatingOrigin`] entity.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The cell of the origin, in the frame it is inside of.
+    pub fn cell(&self) -> GridCell<P> {
+        self.cell
+    }
+
+    /// The frame the origin is inside of, or `None` if it is in the root grid.
+    pub fn frame(&self) -> Option<Entity> {
+        self.frame
+    }
+
+    /// The position of the origin in `frame`, as a cell and an offset from the center of that cell,
+    /// or `None` if `frame` does not contain the origin. The root grid is `None`. The offset can be
+    /// larger than a cell.
+    pub fn position_in(&self, frame: Option<Entity>) -> Option<(GridCell<P>, DVec3)> {
+        self.cells.get(&frame).copied()
+    }
+
+    /// The cell of the root grid nearest the origin.
+    pub fn root_cell(&self, settings: &FloatingOriginSettings) -> GridCell<P> {
+        // Every origin is located in the root grid, see `locate`.
+        let (cell, offset) = self.cells[&None];
+        cell.saturating_add_f64((offset / settings.grid_edge_length()).round())
+    }
+
+    /// The render layers of the origin. Grid entities use the first origin that shares one of their
+    /// render layers.
+    pub fn layers(&self) -> RenderLayers {
+        self.layers
+    }
+
+    /// The [`OriginPriority`] of the origin.
+    pub fn priority(&self) -> OriginPriority {
+        self.priority
+    }
+
+    /// Returns `true` if the position of the origin changed relative to any of the frames it is
+    /// inside of. In [`OriginMode::Grid`], only changing cells counts. When this happens, every
+    /// grid entity using this origin needs to be recomputed.
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+}
+
+/// Tracks every grid entity that has grid children, and the position of every [`FloatingOrigin`]
+/// in each frame between it and the root grid.
+///
+/// Updated every frame by [`update_reference_frames`], before any [`GlobalTransform`]s are
+/// computed from grid positions.
+#[derive(Resource)]
+pub struct ReferenceFrames<P: GridPrecision> {
+    frames: HashMap<Entity, Frame<P>>,
+    /// Every floating origin, sorted by priority, highest first, then by entity.
+    origins: Vec<OriginFrames<P>>,
+    /// Whether an origin was added or removed, or changed render layers or priority this update.
+    origins_changed: bool,
+}
+
+impl<P: GridPrecision> Default for ReferenceFrames<P> {
+    fn default() -> Self {
+        Self {
+            frames: HashMap::default(),
+            origins: Vec::new(),
+            origins_changed: true,
+        }
+    }
+}
+
+impl<P: GridPrecision> ReferenceFrames<P> {
+    /// Get the reference frame defined by `entity`, if it has grid children.
+    pub fn get(&self, entity: Entity) -> Option<&Frame<P>> {
+        self.frames.get(&entity)
+    }
+
+    /// Returns the frame an entity with the given `parent` is inside of. This is `None` when the
+    /// entity is in the root grid.
+    pub fn frame_of(&self, parent: Option<&Parent>) -> Option<Entity> {
+        parent
+            .map(|parent| parent.get())
+            .filter(|parent| self.frames.contains_key(parent))
+    }
+
+    /// Returns `true` if this frame, or any frame it is inside of, moved this update.
+    pub fn frame_changed(&self, mut frame: Option<Entity>) -> bool {
+        while let Some(f) = frame.and_then(|entity| self.frames.get(&entity)) {
+            if f.changed {
+                return true;
+            }
+            frame = f.parent;
+        }
+        false
+    }
+
+    /// Returns the orientation of the axes of this frame, relative to the axes of the root grid.
+    pub fn orientation(&self, frame: Option<Entity>) -> DQuat {
+        frame_orientation(frame, |entity| self.frames.get(&entity).copied())
+    }
+
+    /// Iterates over every [`FloatingOrigin`], sorted by [`OriginPriority`], highest first, then by
+    /// entity.
+    pub fn origins(&self) -> impl Iterator<Item = &OriginFrames<P>> {
+        self.origins.iter()
+    }
+
+    /// Returns the origin that the [`GlobalTransform`] of `entity` should be computed relative to.
+    ///
+    /// An origin is always relative to itself. Every other entity uses the origin with the highest
+    /// [`OriginPriority`] that shares any of its `layers`, or the default [`RenderLayers`] if it
+    /// has none. Returns `None` if no origin matches.
+    ///
+    /// An entity that shares layers with several origins is only placed relative to that one, see
+    /// [`FloatingOrigin`].
+    pub fn origin_for(
+        &self,
+        entity: Entity,
+        layers: Option<&RenderLayers>,
+    ) -> Option<&OriginFrames<P>> {
+        let layers = layers.copied().unwrap_or_default();
+        self.origins
+            .iter()
+            .find(|origin| origin.entity == entity)
+            .or_else(|| {
+                self.origins
+                    .iter()
+                    .find(|origin| origin.layers.intersects(&layers))
+            })
+    }
+
+    /// Returns `true` if any origin was added, removed, or changed its render layers or priority
+    /// this update.
+    /// When this happens, every grid entity needs to be recomputed.
+    pub fn origins_changed(&self) -> bool {
+        self.origins_changed
+    }
+
+    /// Computes the position of a grid entity relative to the grid cell of `origin`, in `f64`,
+    /// along the axes of the root grid. Without an origin, the position is relative to the center
+    /// of the root grid.
+    ///
+    /// The position is accumulated up the frame hierarchy only until it reaches a frame that also
+    /// contains the origin, where the grid cells are subtracted before converting to floating point.
+    pub fn relative_to_origin(
+        &self,
+        settings: &FloatingOriginSettings,
+        origin: Option<&OriginFrames<P>>,
+        cell: GridCell<P>,
+        translation: DVec3,
+        frame: Option<Entity>,
+    ) -> DVec3 {
+        offset_from(
+            settings,
+            origin.map(|origin| &origin.cells),
+            cell,
+            translation,
+            frame,
+            |entity| self.frames.get(&entity).copied(),
+        )
+    }
+}
+
+/// The position of a point in every frame between the frame it is inside of and the root grid, as
+/// a cell and a translation from the center of that cell. The root grid is keyed as `None`.
+pub(crate) type FramePositions<P> = HashMap<Option<Entity>, (GridCell<P>, DVec3)>;
+
+/// Finds the position (`cell`, `translation`) inside `frame` in every frame between it and the root
+/// grid. Frames are looked up with `get_frame`; a missing frame is treated as the root grid.
+pub(crate) fn locate<P: GridPrecision>(
+    settings: &FloatingOriginSettings,
+    mut cell: GridCell<P>,
+    mut translation: DVec3,
+    mut frame: Option<Entity>,
+    get_frame: impl Fn(Entity) -> Option<Frame<P>>,
+) -> FramePositions<P> {
+    let mut positions = HashMap::default();
+    loop {
+        positions.insert(frame, (cell, translation));
+        match frame.and_then(&get_frame) {
+            Some(f) => {
+                translation = f.translation
+                    + f.rotation * (settings.grid_position_double(&cell) + translation);
+                cell = f.cell;
+                frame = f.parent;
+            }
+            None => break,
+        }
+    }
+    positions.entry(None).or_insert((cell, translation));
+    positions
+}
+
+/// Computes the offset from the point at `from` to the position (`cell`, `translation`) inside
+/// `frame`, in `f64`, along the axes of the root grid. Without `from`, the offset is from the
+/// center of the root grid. Frames are looked up with `get_frame`; a missing frame is treated as
+/// the root grid.
+///
+/// The position is accumulated up the frame hierarchy only until it reaches a frame that `from` is
+/// also inside of, where the grid cells are subtracted before converting to floating point.
+pub(crate) fn offset_from<P: GridPrecision>(
+    settings: &FloatingOriginSettings,
+    from: Option<&FramePositions<P>>,
+    mut cell: GridCell<P>,
+    mut translation: DVec3,
+    mut frame: Option<Entity>,
+    get_frame: impl Fn(Entity) -> Option<Frame<P>>,
+) -> DVec3 {
+    loop {
+        if let Some((from_cell, from_translation)) = from.and_then(|from| from.get(&frame)) {
+            let offset =
+                settings.grid_offset_double(from_cell, &cell) + translation - *from_translation;
+            return frame_orientation(frame, &get_frame) * offset;
+        }
+        match frame.and_then(&get_frame) {
+            Some(f) => {
+                translation = f.translation
+                    + f.rotation * (settings.grid_position_double(&cell) + translation);
+                cell = f.cell;
+                frame = f.parent;
+            }
+            // The frame is missing, or `from` is not in any frame. Fall back to the root grid.
+            None if frame.is_some() => frame = None,
+            None => return settings.grid_position_double(&cell) + translation,
+        }
+    }
+}
+
+/// Returns the orientation of the axes of `frame`, relative to the axes of the root grid. Frames
+/// are looked up with `get_frame`.
+pub(crate) fn frame_orientation<P: GridPrecision>(
+    mut frame: Option<Entity>,
+    get_frame: impl Fn(Entity) -> Option<Frame<P>>,
+) -> DQuat {
+    let mut orientation = DQuat::IDENTITY;
+    while let Some(f) = frame.and_then(&get_frame) {
+        orientation = f.rotation * orientation;
+        frame = f.parent;
+    }
+    orientation
+}
+
+/// Rebuilds the [`ReferenceFrames`] resource from the grid hierarchy, and locates every
+/// [`FloatingOrigin`] in every frame between it and the root grid.
+pub fn update_reference_frames<P: GridPrecision>(
+    settings: Res<FloatingOriginSettings>,
+    mut reference_frames: ResMut<ReferenceFrames<P>>,
+    origin_query: Query<
+        (
+            Entity,
+            &GridCell<P>,
+            Changed<GridCell<P>>,
+            &Transform,
+            Changed<Transform>,
+            Option<&Parent>,
+            Option<&RenderLayers>,
+            Option<&OriginPriority>,
+        ),
+        With<FloatingOrigin>,
+    >,
+    frames: Query<
+        (
+            Entity,
+            &GridCell<P>,
+            Changed<GridCell<P>>,
+            &Transform,
+            Changed<Transform>,
+            Option<&Parent>,
+            Option<&RotatingFrame>,
+        ),
+        With<Children>,
+    >,
+    grid_entities: Query<(), With<GridCell<P>>>,
+    (reparented, removed_parents): (
+        Query<(Entity, &Parent), (With<GridCell<P>>, Changed<Parent>)>,
+        RemovedComponents<Parent>,
+    ),
+    mut origin_events: EventWriter<FloatingOriginShifted<P>>,
+) {
+    for (entity, parent) in &reparented {
+        if !grid_entities.contains(parent.get()) {
+            warn!(
+                "malformed hierarchy: {:?} is a grid entity, but its parent {:?} is not, so it is \
+                placed in the root grid and the transform of its parent is ignored",
+                entity,
+                parent.get()
+            );
+        }
+    }
+
+    // A frame that moved to another frame moved everything inside of it.
+    let reparented: HashSet<Entity> = reparented
+        .iter()
+        .map(|(entity, _)| entity)
+        .chain(removed_parents.iter())
+        .collect();
+
+    let reference_frames = reference_frames.as_mut();
+    reference_frames.frames.clear();
+    for (entity, cell, cell_changed, transform, transform_changed, parent, rotating) in &frames {
+        reference_frames.frames.insert(
+            entity,
+            Frame {
+                parent: parent
+                    .map(|parent| parent.get())
+                    .filter(|parent| grid_entities.contains(*parent)),
+                cell: *cell,
+                translation: transform.translation.as_dvec3(),
+                rotation: match rotating {
+                    Some(_) => transform.rotation.as_f64(),
+                    None => DQuat::IDENTITY,
+                },
+                changed: cell_changed || transform_changed || reparented.contains(&entity),
+            },
+        );
+    }
+
+    let mut origins = Vec::with_capacity(reference_frames.origins.len());
+    let camera_relative = settings.origin_mode() == OriginMode::CameraRelative;
+    for (
+        entity,
+        origin_cell,
+        cell_changed,
+        transform,
+        transform_changed,
+        origin_parent,
+        layers,
+        priority,
+    ) in &origin_query
+    {
+        let origin_frame = reference_frames.frame_of(origin_parent);
+        let changed = cell_changed
+            || (camera_relative && transform_changed)
+            || reference_frames.frame_changed(origin_frame);
+
+        // In camera-relative mode, the origin is at its exact position rather than the center of
+        // its cell.
+        let offset = if camera_relative {
+            transform.translation.as_dvec3()
+        } else {
+            DVec3::ZERO
+        };
+        let cells = locate(&settings, *origin_cell, offset, origin_frame, |entity| {
+            reference_frames.frames.get(&entity).copied()
+        });
+
+        origins.push(OriginFrames {
+            entity,
+            cell: *origin_cell,
+            frame: origin_frame,
+            layers: layers.copied().unwrap_or_default(),
+            priority: priority.copied().unwrap_or_default(),
+            cells,
+            changed,
+        });
+    }
+    origins.sort_by_key(|origin| (std::cmp::Reverse(origin.priority), origin.entity));
+
+    reference_frames.origins_changed = origins.len() != reference_frames.origins.len()
+        || origins
+            .iter()
+            .zip(reference_frames.origins.iter())
+            .any(|(new, old)| {
+                new.entity != old.entity || new.layers != old.layers || new.priority != old.priority
+            });
+    if reference_frames.origins_changed {
+        for (i, a) in origins.iter().enumerate() {
+            for b in &origins[i + 1..] {
+                if a.priority == b.priority && a.layers.intersects(&b.layers) {
+                    warn!(
+                        "the floating origins {:?} and {:?} share render layers and priority, \
+                        entities on the shared layers use {:?}. Give one of them a higher \
+                        OriginPriority to choose",
+                        a.entity, b.entity, a.entity
+                    );
+                }
+            }
+        }
+    }
+    for origin in &origins {
+        let old = reference_frames
+            .origins
+            .iter()
+            .find(|old| old.entity == origin.entity);
+        if let Some(old) = old {
+            if old.cell != origin.cell || old.frame != origin.frame {
+                origin_events.send(FloatingOriginShifted {
+                    origin: origin.entity,
+                    old_cell: old.cell,
+                    new_cell: origin.cell,
+                });
+            }
+        }
+    }
+    reference_frames.origins = origins;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FloatingOriginPlugin;
+
+    fn spawn_frame(app: &mut App, parent: Option<Entity>, cell: GridCell<i64>, x: f32) -> Entity {
+        let entity = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_xyz(x, 0.0, 0.0)),
+                cell,
+            ))
+            .id();
+        if let Some(parent) = parent {
+            app.world.entity_mut(parent).push_children(&[entity]);
+        }
+        entity
+    }
+
+    fn translation(app: &App, entity: Entity) -> Vec3 {
+        app.world
+            .get::<GlobalTransform>(entity)
+            .unwrap()
+            .translation()
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-3,
+            "expected {expected}, found {actual}"
+        );
+    }
+
+    /// A child that moves past the edge of its cell is recentered on the grid of its parent, and
+    /// its global transform follows every frame above it.
+    #[test]
+    fn nested_frames() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let planet = spawn_frame(&mut app, None, GridCell::new(5, 0, 0), 0.0);
+        let ship = spawn_frame(&mut app, Some(planet), GridCell::ZERO, 100.0);
+        let crew = spawn_frame(&mut app, Some(ship), GridCell::ZERO, 1.0);
+        app.update();
+        assert_eq!(translation(&app, ship), Vec3::new(50_100.0, 0.0, 0.0));
+        assert_eq!(translation(&app, crew), Vec3::new(50_101.0, 0.0, 0.0));
+
+        // Past `maximum_distance_from_origin`, the ship moves into the next cell of the planet's
+        // grid, not the root grid.
+        app.world.get_mut::<Transform>(ship).unwrap().translation.x = 12_000.0;
+        app.update();
+        assert_eq!(
+            app.world.get::<GridCell<i64>>(ship),
+            Some(&GridCell::new(1, 0, 0))
+        );
+        assert_eq!(
+            app.world.get::<Transform>(ship).unwrap().translation,
+            Vec3::new(2_000.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            app.world.get::<GridCell<i64>>(planet),
+            Some(&GridCell::new(5, 0, 0))
+        );
+        assert_eq!(translation(&app, ship), Vec3::new(62_000.0, 0.0, 0.0));
+        assert_eq!(translation(&app, crew), Vec3::new(62_001.0, 0.0, 0.0));
+
+        // Moving the planet moves everything inside of it.
+        *app.world.get_mut::<GridCell<i64>>(planet).unwrap() = GridCell::new(-5, 0, 0);
+        app.update();
+        assert_eq!(translation(&app, ship), Vec3::new(-38_000.0, 0.0, 0.0));
+        assert_eq!(translation(&app, crew), Vec3::new(-37_999.0, 0.0, 0.0));
+    }
+
+    /// Moving a child to another frame keeps its local position, relative to the new frame.
+    #[test]
+    fn reparent_between_frames() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let a = spawn_frame(&mut app, None, GridCell::new(1, 0, 0), 0.0);
+        let b = spawn_frame(&mut app, None, GridCell::new(-1, 0, 0), 0.0);
+        let child = spawn_frame(&mut app, Some(a), GridCell::new(0, 1, 0), 1.0);
+        // Keeps `b` a frame once `child` leaves `a`.
+        spawn_frame(&mut app, Some(b), GridCell::ZERO, 0.0);
+        app.update();
+        assert_eq!(translation(&app, child), Vec3::new(10_001.0, 10_000.0, 0.0));
+
+        app.world.entity_mut(b).push_children(&[child]);
+        app.update();
+        assert_eq!(translation(&app, child), Vec3::new(-9_999.0, 10_000.0, 0.0));
+
+        // Out of every frame, into the root grid.
+        app.world.entity_mut(b).remove_children(&[child]);
+        app.update();
+        assert_eq!(translation(&app, child), Vec3::new(1.0, 10_000.0, 0.0));
+    }
+
+    /// The grid of a rotating frame turns with it, and so do the entities inside of it.
+    #[test]
+    fn rotating_frame() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        app.world.spawn((
+            TransformBundle::default(),
+            GridCell::<i64>::ZERO,
+            FloatingOrigin,
+        ));
+        let planet = spawn_frame(&mut app, None, GridCell::new(1, 0, 0), 0.0);
+        app.world.entity_mut(planet).insert(RotatingFrame);
+        let moon = spawn_frame(&mut app, Some(planet), GridCell::new(1, 0, 0), 10.0);
+        app.update();
+        assert_near(translation(&app, moon), Vec3::new(20_010.0, 0.0, 0.0));
+
+        // A quarter turn around the y axis turns +x into -z.
+        let turn = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        app.world.get_mut::<Transform>(planet).unwrap().rotation = turn;
+        app.update();
+        assert_near(translation(&app, moon), Vec3::new(10_000.0, 0.0, -10_010.0));
+        let rotation = app
+            .world
+            .get::<GlobalTransform>(moon)
+            .unwrap()
+            .to_scale_rotation_translation()
+            .1;
+        assert!(rotation.angle_between(turn) < 1e-3);
+    }
+
+    /// With the origin inside a rotating frame, everything else is placed relative to the
+    /// origin's position in that frame.
+    #[test]
+    fn origin_in_rotating_frame() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i64>::default());
+        let planet = spawn_frame(&mut app, None, GridCell::new(1, 0, 0), 0.0);
+        app.world.get_mut::<Transform>(planet).unwrap().rotation =
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        app.world.entity_mut(planet).insert(RotatingFrame);
+        let origin = spawn_frame(&mut app, Some(planet), GridCell::new(1, 0, 0), 0.0);
+        app.world.entity_mut(origin).insert(FloatingOrigin);
+        let neighbor = spawn_frame(&mut app, Some(planet), GridCell::new(1, 0, 0), 10.0);
+        let outside = spawn_frame(&mut app, None, GridCell::new(1, 0, 0), 0.0);
+        app.update();
+
+        // The origin is a cell along the planet's x axis, which points along -z.
+        assert_near(translation(&app, origin), Vec3::ZERO);
+        assert_near(translation(&app, neighbor), Vec3::new(0.0, 0.0, -10.0));
+        assert_near(translation(&app, outside), Vec3::new(0.0, 0.0, 10_000.0));
+        assert_near(translation(&app, planet), Vec3::new(0.0, 0.0, 10_000.0));
+    }
+}
@@ -0,0 +1,149 @@
+//! Precise distance queries between grid entities.
+
+use bevy::{ecs::system::SystemParam, math::DVec3, prelude::*};
+
+use crate::{grid_index::GridIndex, precision::GridPrecision, FloatingOriginSettings, GridCell};
+
+/// A [`SystemParam`] for finding grid entities near a position, using `f64` math across cell
+/// boundaries.
+///
+/// Positions are given as a [`GridCell`] and a translation within that cell, the same way entities
+/// are positioned in the grid, so queries are just as precise a billion light years from the
+/// origin as they are next to it. Candidate entities are found with the [`GridIndex`], so only
+/// entities in nearby cells are tested.
+///
+/// Only entities in the root grid are considered, see [`GridIndex`].
+#[derive(SystemParam)]
+pub struct SpatialQuery<'w, 's, P: GridPrecision> {
+    settings: Res<'w, FloatingOriginSettings>,
+    index: Res<'w, GridIndex<P>>,
+    entities: Query<'w, 's, (&'static GridCell<P>, &'static Transform)>,
+}
+
+impl<'w, 's, P: GridPrecision> SpatialQuery<'w, 's, P> {
+    /// Returns the offset from the position (`cell`, `translation`) to `entity`, if it is a grid
+    /// entity.
+    pub fn offset_to(&self, cell: GridCell<P>, translation: Vec3, entity: Entity) -> Option<DVec3> {
+        let (entity_cell, entity_transform) = self.entities.get(entity).ok()?;
+        Some(
+            self.settings.grid_offset_double(&cell, entity_cell)
+                + entity_transform.translation.as_dvec3()
+                - translation.as_dvec3(),
+        )
+    }
+
+    /// Iterates over every entity within `radius` of the position (`cell`, `translation`), along
+    /// with the offset from that position to the entity.
+    pub fn within_radius(
+        &self,
+        cell: GridCell<P>,
+        translation: Vec3,
+        radius: f64,
+    ) -> impl Iterator<Item = (Entity, DVec3)> + '_ {
+        // A radius too large to count in cells reaches every occupied cell.
+        let (near, all) = match self.cell_radius(radius) {
+            Some(cell_radius) => (Some(self.index.entities_within(cell, cell_radius)), None),
+            None => {
+                let all = self
+                    .index
+                    .occupied_cells()
+                    .flat_map(|(_, entities)| entities.iter().copied());
+                (None, Some(all))
+            }
+        };
+        near.into_iter()
+            .flatten()
+            .chain(all.into_iter().flatten())
+            .filter_map(move |entity| {
+                let offset = self.offset_to(cell, translation, entity)?;
+                (offset.length_squared() <= radius * radius).then_some((entity, offset))
+            })
+    }
+
+    /// Returns the entity nearest to the position (`cell`, `translation`) within `radius`, and the
+    /// distance to it.
+    pub fn nearest_within(
+        &self,
+        cell: GridCell<P>,
+        translation: Vec3,
+        radius: f64,
+    ) -> Option<(Entity, f64)> {
+        self.within_radius(cell, translation, radius)
+            .map(|(entity, offset)| (entity, offset.length()))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// Returns the entity nearest to the position (`cell`, `translation`), and the distance to it.
+    ///
+    /// The search starts in the surrounding cells and expands outwards until an entity is found.
+    pub fn nearest(&self, cell: GridCell<P>, translation: Vec3) -> Option<(Entity, f64)> {
+        let edge_length = self.settings.grid_edge_length();
+        let mut radius = edge_length;
+        loop {
+            if let Some(nearest) = self.nearest_within(cell, translation, radius) {
+                return Some(nearest);
+            }
+            // Once the search volume holds more cells than are occupied, the index scans every
+            // occupied cell, so there is nothing left to find by expanding further.
+            let edge = match self.cell_radius(radius) {
+                Some(cell_radius) => 2.0 * cell_radius as f64 + 1.0,
+                None => f64::INFINITY,
+            };
+            if edge * edge * edge >= self.index.occupied_cell_count() as f64 {
+                return self.nearest_within(cell, translation, f64::INFINITY);
+            }
+            radius *= 2.0;
+        }
+    }
+
+    /// The number of cells around a position that need to be searched to find every entity within
+    /// `radius`. Both the position and the entities can be offset from the center of their cells.
+    /// Returns `None` if the radius is too large to count in cells, in which case every occupied
+    /// cell needs to be searched.
+    fn cell_radius(&self, radius: f64) -> Option<u32> {
+        let max_offset = 2.0 * self.settings.maximum_distance_from_origin();
+        let cell_radius = ((radius + max_offset) / self.settings.grid_edge_length()).ceil();
+        (cell_radius <= u32::MAX as f64).then_some(cell_radius as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::FloatingOriginPlugin;
+
+    /// Entities further away than can be counted in cells are still found.
+    #[test]
+    fn nearest_across_the_grid() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i128>::default());
+        let far = app
+            .world
+            .spawn((
+                TransformBundle::default(),
+                GridCell::<i128>::new(i128::MAX / 2, 0, 0),
+            ))
+            .id();
+        app.update();
+
+        let mut state = SystemState::<SpatialQuery<i128>>::new(&mut app.world);
+        let query = state.get(&app.world);
+        let expected = (i128::MAX / 2) as f64 * query.settings.grid_edge_length();
+        let (nearest, distance) = query.nearest(GridCell::ZERO, Vec3::ZERO).unwrap();
+        assert_eq!(nearest, far);
+        assert_eq!(distance, expected);
+        assert_eq!(
+            query
+                .within_radius(GridCell::ZERO, Vec3::ZERO, f64::INFINITY)
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>(),
+            vec![far]
+        );
+        assert_eq!(
+            query.nearest_within(GridCell::ZERO, Vec3::ZERO, expected / 2.0),
+            None
+        );
+    }
+}
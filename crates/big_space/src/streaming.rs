@@ -0,0 +1,337 @@
+//! Streaming grid entities in and out around the floating origin.
+//!
+//! The [`StreamingPlugin`] keeps every cell of the root grid within
+//! [`StreamingSettings::load_radius`] cells of a [`FloatingOrigin`](crate::FloatingOrigin) loaded,
+//! by asking a [`CellStreamer`] to populate it, and unloads cells once they are more than
+//! [`StreamingSettings::unload_radius`] cells away from every origin. Only the cells around the
+//! origins are ever resident, so an effectively infinite grid can be filled procedurally.
+//!
+//! Entities are tracked by the cell they were loaded for, and are unloaded with that cell even if
+//! they moved since. Entities that are despawned while their cell is loaded are skipped.
+
+use std::{marker::PhantomData, sync::Mutex};
+
+use bevy::{
+    hierarchy::despawn_with_children_recursive,
+    math::DVec3,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    precision::GridPrecision, reference_frame::ReferenceFrames, FloatingOriginSettings, GridCell,
+};
+
+/// Populates cells of the grid as they come within range of a floating origin, and cleans them up
+/// once they are out of range. See the [module docs](crate::streaming).
+///
+/// This is implemented for closures that load a cell, in which case unloaded cells are despawned.
+pub trait CellStreamer<P: GridPrecision>: Send + Sync + 'static {
+    /// Spawns the contents of `cell`, and returns the spawned entities. Entities should be placed
+    /// in `cell`, but the returned entities may also be parents of grid entities, like a
+    /// [reference frame](crate::reference_frame).
+    fn load(&mut self, world: &mut World, cell: GridCell<P>) -> Vec<Entity>;
+
+    /// Removes the `entities` that were loaded for `cell` from the world. By default they are
+    /// despawned, along with their children. Override this to save them first, so they can be
+    /// restored by the next [`load`](Self::load) of `cell`.
+    fn unload(&mut self, world: &mut World, cell: GridCell<P>, entities: Vec<Entity>) {
+        let _ = cell;
+        for entity in entities {
+            if world.get_entity(entity).is_some() {
+                despawn_with_children_recursive(world, entity);
+            }
+        }
+    }
+}
+
+impl<P, F> CellStreamer<P> for F
+where
+    P: GridPrecision,
+    F: FnMut(&mut World, GridCell<P>) -> Vec<Entity> + Send + Sync + 'static,
+{
+    fn load(&mut self, world: &mut World, cell: GridCell<P>) -> Vec<Entity> {
+        self(world, cell)
+    }
+}
+
+/// How far from the floating origins cells are streamed in and out.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingSettings {
+    /// Cells within this many cells of an origin, along every axis, are loaded.
+    pub load_radius: u32,
+    /// Cells more than this many cells from every origin, along any axis, are unloaded. This must
+    /// be at least the `load_radius`. Keeping it larger stops cells from being reloaded over and
+    /// over by an origin moving back and forth across a cell boundary.
+    pub unload_radius: u32,
+    /// The most cells loaded in one update. The cells nearest an origin are loaded first.
+    pub loads_per_update: usize,
+}
+
+impl StreamingSettings {
+    /// Loads cells within `load_radius` cells of an origin, and unloads them one cell further out.
+    /// Every cell in range is loaded in the same update.
+    pub fn new(load_radius: u32) -> Self {
+        Self {
+            load_radius,
+            unload_radius: load_radius + 1,
+            loads_per_update: usize::MAX,
+        }
+    }
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+/// Streams cells of the root grid in and out around every floating origin with a
+/// [`CellStreamer`]. See the [module docs](crate::streaming).
+pub struct StreamingPlugin<P: GridPrecision, S: CellStreamer<P>> {
+    pub settings: StreamingSettings,
+    /// Moved into the [`Streamer`] resource when the plugin is built, as plugins are only borrowed
+    /// then.
+    streamer: Mutex<Option<S>>,
+    phantom: PhantomData<P>,
+}
+
+impl<P: GridPrecision, S: CellStreamer<P>> StreamingPlugin<P, S> {
+    pub fn new(settings: StreamingSettings, streamer: S) -> Self {
+        Self {
+            settings,
+            streamer: Mutex::new(Some(streamer)),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<P: GridPrecision, S: CellStreamer<P>> Plugin for StreamingPlugin<P, S> {
+    fn build(&self, app: &mut App) {
+        assert!(
+            self.settings.unload_radius >= self.settings.load_radius,
+            "The unload radius of the StreamingSettings must be at least the load radius, \
+            otherwise cells would be unloaded as soon as they are loaded"
+        );
+        let streamer = self
+            .streamer
+            .lock()
+            .unwrap()
+            .take()
+            .expect("The StreamingPlugin can only be built once");
+        app.insert_resource(self.settings)
+            .insert_resource(Streamer::<P, S> {
+                streamer,
+                phantom: PhantomData,
+            })
+            .init_resource::<StreamedCells<P>>()
+            .add_system_to_stage(CoreStage::PreUpdate, stream_cells::<P, S>);
+    }
+}
+
+/// Holds the [`CellStreamer`] added by the [`StreamingPlugin`].
+#[derive(Resource)]
+struct Streamer<P: GridPrecision, S: CellStreamer<P>> {
+    streamer: S,
+    phantom: PhantomData<P>,
+}
+
+/// The cells loaded by the [`StreamingPlugin`], and the entities loaded for each of them.
+#[derive(Resource)]
+pub struct StreamedCells<P: GridPrecision> {
+    cells: HashMap<GridCell<P>, Vec<Entity>>,
+    /// Whether every cell in range was loaded in the last update.
+    complete: bool,
+}
+
+impl<P: GridPrecision> Default for StreamedCells<P> {
+    fn default() -> Self {
+        Self {
+            cells: HashMap::default(),
+            complete: false,
+        }
+    }
+}
+
+impl<P: GridPrecision> StreamedCells<P> {
+    /// Returns `true` if `cell` is loaded.
+    pub fn is_loaded(&self, cell: &GridCell<P>) -> bool {
+        self.cells.contains_key(cell)
+    }
+
+    /// Iterates over the entities loaded for `cell`.
+    pub fn entities_in(&self, cell: &GridCell<P>) -> impl Iterator<Item = Entity> + '_ {
+        self.cells.get(cell).into_iter().flatten().copied()
+    }
+
+    /// Iterates over every loaded cell.
+    pub fn loaded_cells(&self) -> impl Iterator<Item = &GridCell<P>> {
+        self.cells.keys()
+    }
+
+    /// The number of loaded cells.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns `true` if no cells are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/// Loads the cells within range of every floating origin, nearest first, and unloads the cells
+/// that are out of range of all of them.
+///
+/// This runs in [`CoreStage::PreUpdate`], using the positions of the origins from the end of the
+/// last update, so loaded entities are in the world for the whole update.
+pub fn stream_cells<P: GridPrecision, S: CellStreamer<P>>(world: &mut World) {
+    let settings = world.resource::<FloatingOriginSettings>().clone();
+    let streaming = *world.resource::<StreamingSettings>();
+    let reference_frames = world.resource::<ReferenceFrames<P>>();
+    if world.resource::<StreamedCells<P>>().complete
+        && !reference_frames.origins_changed()
+        && !reference_frames.origins().any(|origin| origin.is_changed())
+    {
+        return;
+    }
+
+    let edge = settings.grid_edge_length();
+    let centers: Vec<GridCell<P>> = reference_frames
+        .origins()
+        .map(|origin| origin.root_cell(&settings))
+        .collect();
+    // The number of cells between `cell` and the nearest origin, along the furthest axis.
+    let distance = |cell: &GridCell<P>| {
+        centers
+            .iter()
+            .map(|center| {
+                (settings.grid_offset_double(center, cell) / edge)
+                    .abs()
+                    .max_element()
+            })
+            .fold(f64::INFINITY, f64::min)
+    };
+
+    world.resource_scope(|world, mut streamer: Mut<Streamer<P, S>>| {
+        let unloaded: Vec<_> = {
+            let mut streamed = world.resource_mut::<StreamedCells<P>>();
+            let out_of_range: Vec<_> = streamed
+                .cells
+                .keys()
+                .filter(|cell| distance(cell) > streaming.unload_radius as f64)
+                .copied()
+                .collect();
+            out_of_range
+                .into_iter()
+                .filter_map(|cell| streamed.cells.remove_entry(&cell))
+                .collect()
+        };
+        for (cell, entities) in unloaded {
+            streamer.streamer.unload(world, cell, entities);
+        }
+
+        let streamed = world.resource::<StreamedCells<P>>();
+        let r = streaming.load_radius as i64;
+        let mut in_range = HashSet::default();
+        for center in &centers {
+            for x in -r..=r {
+                for y in -r..=r {
+                    for z in -r..=r {
+                        // Cells past the edge of the grid are skipped, rather than wrapping around.
+                        let offset = DVec3::new(x as f64, y as f64, z as f64);
+                        if let Some(cell) = center.checked_add_f64(offset) {
+                            if !streamed.is_loaded(&cell) {
+                                in_range.insert(cell);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let mut to_load: Vec<_> = in_range
+            .into_iter()
+            .map(|cell| (distance(&cell), cell))
+            .collect();
+        to_load.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        let complete = to_load.len() <= streaming.loads_per_update;
+        for (_, cell) in to_load.into_iter().take(streaming.loads_per_update) {
+            let entities = streamer.streamer.load(world, cell);
+            world
+                .resource_mut::<StreamedCells<P>>()
+                .cells
+                .insert(cell, entities);
+        }
+        world.resource_mut::<StreamedCells<P>>().complete = complete;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FloatingOrigin, FloatingOriginPlugin};
+
+    #[test]
+    fn stream_around_origin() {
+        let mut app = App::new();
+        app.add_plugin(FloatingOriginPlugin::<i128>::default())
+            .add_plugin(StreamingPlugin::<i128, _>::new(
+                StreamingSettings {
+                    load_radius: 1,
+                    unload_radius: 2,
+                    loads_per_update: 20,
+                },
+                |world: &mut World, cell: GridCell<i128>| {
+                    vec![world.spawn((TransformBundle::default(), cell)).id()]
+                },
+            ));
+        let far = GridCell::new(i128::MAX / 2, 0, 0);
+        let origin = app
+            .world
+            .spawn((TransformBundle::default(), far, FloatingOrigin))
+            .id();
+        let loaded = |app: &App| app.world.resource::<StreamedCells<i128>>().len();
+
+        // The 27 cells around the origin take two updates to load, nearest first.
+        app.update();
+        assert_eq!(loaded(&app), 20);
+        assert!(app.world.resource::<StreamedCells<i128>>().is_loaded(&far));
+        app.update();
+        assert_eq!(loaded(&app), 27);
+        app.update();
+        assert_eq!(loaded(&app), 27);
+
+        // Cells are streamed around where the origin was at the end of the last update. Moving one
+        // cell keeps the cells behind the origin within the unload radius.
+        let next = far + GridCell::new(1, 0, 0);
+        *app.world.get_mut::<GridCell<i128>>(origin).unwrap() = next;
+        app.update();
+        app.update();
+        assert_eq!(loaded(&app), 36);
+
+        // Moving far away unloads everything, and despawns what was loaded.
+        let away = GridCell::new(-i128::MAX / 4, 0, 0);
+        *app.world.get_mut::<GridCell<i128>>(origin).unwrap() = away;
+        app.update();
+        app.update();
+        app.update();
+        let streamed = app.world.resource::<StreamedCells<i128>>();
+        assert_eq!(streamed.len(), 27);
+        assert!(streamed.loaded_cells().all(|cell| cell.x < 0));
+        assert_eq!(app.world.entities().len(), 27 + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "unload radius")]
+    fn unload_inside_load_radius() {
+        App::new().add_plugin(StreamingPlugin::<i128, _>::new(
+            StreamingSettings {
+                load_radius: 2,
+                unload_radius: 1,
+                ..default()
+            },
+            |_: &mut World, _: GridCell<i128>| Vec::new(),
+        ));
+    }
+}
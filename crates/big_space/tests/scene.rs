@@ -0,0 +1,111 @@
+//! Saving and loading grid entities, with exact positions.
+
+use bevy::{
+    ecs::entity::EntityMap,
+    prelude::*,
+    reflect::serde::{ReflectSerializer, UntypedReflectDeserializer},
+    scene::serde::SceneDeserializer,
+};
+use big_space::{
+    fixed_point::FixedTranslation,
+    overflow::GridOverflowPolicy,
+    precision::{GridPrecision, I256},
+    reference_frame::RotatingFrame,
+    FloatingOrigin, FloatingOriginPlugin, FloatingOriginSettings, GridCell,
+};
+use serde::de::DeserializeSeed;
+
+fn app<P: GridPrecision>() -> App {
+    let mut app = App::new();
+    app.add_plugin(FloatingOriginPlugin::<P>::default())
+        .register_type::<Vec3>()
+        .register_type::<Quat>();
+    app
+}
+
+/// Saves a floating origin and a child in its reference frame to a RON scene, then loads the
+/// scene into a new world.
+fn scene_round_trip<P: GridPrecision>(cell: GridCell<P>) {
+    let mut app = app::<P>();
+    let transform = Transform::from_xyz(1.25, -0.5, 3.0).with_rotation(Quat::from_rotation_y(1.0));
+    let fixed = FixedTranslation::new(i64::MAX, i64::MIN, 3);
+    let origin = app
+        .world
+        .spawn((cell, transform, fixed, FloatingOrigin, RotatingFrame))
+        .id();
+
+    let registry = app.world.resource::<AppTypeRegistry>().clone();
+    let scene = DynamicScene::from_world(&app.world, &registry);
+    let serialized = scene.serialize_ron(&registry).unwrap();
+
+    let mut deserializer = ron::de::Deserializer::from_str(&serialized).unwrap();
+    let scene = SceneDeserializer {
+        type_registry: &registry.read(),
+    }
+    .deserialize(&mut deserializer)
+    .unwrap();
+
+    let mut loaded = app::<P>();
+    let mut entity_map = EntityMap::default();
+    scene
+        .write_to_world(&mut loaded.world, &mut entity_map)
+        .unwrap();
+    let entity = loaded.world.entity(entity_map.get(origin).unwrap());
+
+    assert_eq!(entity.get::<GridCell<P>>(), Some(&cell));
+    assert_eq!(entity.get::<Transform>(), Some(&transform));
+    assert_eq!(entity.get::<FixedTranslation>(), Some(&fixed));
+    assert!(entity.contains::<FloatingOrigin>());
+    assert!(entity.contains::<RotatingFrame>());
+}
+
+#[test]
+fn scene_round_trip_i64() {
+    scene_round_trip(GridCell::<i64>::new(i64::MAX, i64::MIN, -1));
+}
+
+#[test]
+fn scene_round_trip_i128() {
+    scene_round_trip(GridCell::<i128>::new(i128::MAX, i128::MIN, -1));
+}
+
+#[test]
+fn scene_round_trip_i256() {
+    scene_round_trip(GridCell::new(I256::MAX, I256::MIN, I256::from(-1)));
+}
+
+#[test]
+fn settings_round_trip() {
+    let app = app::<i128>();
+    let registry = app.world.resource::<AppTypeRegistry>().read();
+    let settings =
+        FloatingOriginSettings::new(0.1, 1e-9).with_overflow_policy(GridOverflowPolicy::EmitEvent);
+
+    let serialized = ron::to_string(&ReflectSerializer::new(&settings, &registry)).unwrap();
+    let mut deserializer = ron::de::Deserializer::from_str(&serialized).unwrap();
+    let reflected = UntypedReflectDeserializer::new(&registry)
+        .deserialize(&mut deserializer)
+        .unwrap();
+    let mut loaded = FloatingOriginSettings::default();
+    loaded.apply(&*reflected);
+
+    assert_eq!(loaded.grid_edge_length(), settings.grid_edge_length());
+    assert_eq!(
+        loaded.maximum_distance_from_origin(),
+        settings.maximum_distance_from_origin()
+    );
+    assert_eq!(loaded.overflow_policy(), settings.overflow_policy());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let cell = GridCell::new(I256::MIN, I256::from(i128::MAX), I256::MAX);
+    let serialized = ron::to_string(&cell).unwrap();
+    assert_eq!(ron::from_str::<GridCell<I256>>(&serialized).unwrap(), cell);
+
+    let settings = FloatingOriginSettings::new(0.1, 1e-9);
+    let serialized = ron::to_string(&settings).unwrap();
+    let loaded: FloatingOriginSettings = ron::from_str(&serialized).unwrap();
+    assert_eq!(loaded.grid_edge_length(), settings.grid_edge_length());
+}
@@ -3,6 +3,7 @@ use bevy::{
     reflect::TypeUuid,
     render::render_resource::{AsBindGroup, ShaderRef},
 };
+use big_space::collision::Collider;
 
 pub struct BodyPlugin;
 impl Plugin for BodyPlugin {
@@ -55,7 +56,10 @@ impl Body {
             transform.scale = Vec3::splat(body.radius);
         }
         for (entity, body, mut transform) in no_mesh_bodies.iter_mut() {
-            commands.entity(entity).insert(base_body_mesh.0.clone());
+            // The mesh and collider are unit spheres, scaled up to the radius of the body.
+            commands
+                .entity(entity)
+                .insert((base_body_mesh.0.clone(), Collider::sphere(1.0)));
             transform.scale = Vec3::splat(body.radius);
         }
     }
@@ -1,9 +1,33 @@
-use bevy::{input::mouse::MouseMotion, prelude::*, render::primitives::Aabb};
+use bevy::{input::mouse::MouseMotion, prelude::*, render::primitives::Aabb, time::FixedTimesteps};
+use big_space::{
+    collision::{detect_collisions, Collision},
+    fixed_timestep::{FixedUpdateStage, FIXED_TIMESTEP_LABEL},
+    kinematics::GridVelocity,
+    spatial_query::SpatialQuery,
+    FloatingOriginSystem, GridCell,
+};
 
 pub struct CameraControllerPlugin;
 impl Plugin for CameraControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(camera_controller);
+        // The camera is simulated at a fixed timestep, so it moves the same at any frame rate.
+        // Mouse motion is read every frame, as the fixed timestep may not run at all in some
+        // frames.
+        app.init_resource::<MouseLook>()
+            .add_system(accumulate_mouse_motion)
+            .add_system_to_stage(
+                FixedUpdateStage,
+                nearest_object_distance.before(camera_controller),
+            )
+            .add_system_to_stage(
+                FixedUpdateStage,
+                camera_controller.before(FloatingOriginSystem::Integrate),
+            )
+            // Collisions are detected after every step, so they are read in the same step.
+            .add_system_to_stage(
+                FixedUpdateStage,
+                camera_collisions.after(detect_collisions::<i128>),
+            );
     }
 }
 
@@ -11,40 +35,75 @@ impl Plugin for CameraControllerPlugin {
 pub struct CameraController {
     pub top_speed: f32,
     pub jerk: f32,
+    nearest_object: f64,
 }
 impl CameraController {
     pub(crate) fn new(top_speed: f32, jerk: f32) -> CameraController {
-        CameraController { top_speed, jerk }
+        CameraController {
+            top_speed,
+            jerk,
+            nearest_object: f64::MAX,
+        }
     }
 }
 
 #[derive(Component)]
 pub struct IgnoreCamDist;
 
+/// The mouse motion since the camera was last rotated.
+#[derive(Resource, Default)]
+pub struct MouseLook(Vec2);
+
+pub fn accumulate_mouse_motion(mut mouse: EventReader<MouseMotion>, mut look: ResMut<MouseLook>) {
+    for motion in mouse.iter() {
+        look.0 += motion.delta;
+    }
+}
+
+/// Finds the distance from the camera to the surface of the nearest object, which is used to limit
+/// the speed of the camera.
+pub fn nearest_object_distance(
+    mut camera: Query<(Entity, &Transform, &GridCell<i128>, &mut CameraController)>,
+    spatial_query: SpatialQuery<i128>,
+    objects: Query<(&Transform, &Aabb), Without<IgnoreCamDist>>,
+) {
+    let (camera_entity, camera_transform, camera_cell, mut controller) = camera.single_mut();
+
+    let mut nearest_object = f64::MAX;
+    for (entity, offset) in spatial_query.within_radius(
+        *camera_cell,
+        camera_transform.translation,
+        controller.top_speed as f64,
+    ) {
+        if entity == camera_entity {
+            continue;
+        }
+        if let Ok((transform, aabb)) = objects.get(entity) {
+            let center = offset
+                + (transform.rotation * (transform.scale * Vec3::from(aabb.center))).as_dvec3();
+            let distance = center.length()
+                - (aabb.half_extents.max_element() * transform.scale.max_element()) as f64;
+            if distance < 0.0 {
+                continue;
+            }
+            nearest_object = nearest_object.min(distance);
+        }
+    }
+    controller.nearest_object = nearest_object;
+}
+
 pub fn camera_controller(
-    time: Res<Time>,
+    timesteps: Res<FixedTimesteps>,
     keyboard: Res<Input<KeyCode>>,
-    mut mouse: EventReader<MouseMotion>,
-    mut camera: Query<(&mut Transform, &GlobalTransform, &mut CameraController)>,
+    mut look: ResMut<MouseLook>,
+    mut camera: Query<(&mut Transform, &mut GridVelocity, &CameraController)>,
     mut current_speed: Local<Vec3>,
-    objects: Query<(&GlobalTransform, &Aabb), Without<IgnoreCamDist>>,
     mut camera_target: Local<Transform>,
 ) {
-    let (mut camera_transform, cam_global_transform, controller) = camera.single_mut();
-
-    let mut nearest_object = f32::MAX;
-    for (transform, aabb) in &objects {
-        let distance = (transform.translation() + Vec3::from(aabb.center)
-            - cam_global_transform.translation())
-        .length()
-            - aabb.half_extents.max_element();
-        if distance < 0.0 {
-            continue;
-        }
-        nearest_object = nearest_object.min(distance);
-    }
+    let (mut camera_transform, mut velocity, controller) = camera.single_mut();
+    let delta_seconds = timesteps.get(FIXED_TIMESTEP_LABEL).unwrap().step() as f32;
 
-    let top_speed = nearest_object.clamp(2.0, controller.top_speed);
+    let top_speed = (controller.nearest_object as f32).clamp(2.0, controller.top_speed);
 
     let mut target = Vec3::ZERO;
 
@@ -82,17 +141,35 @@ pub fn camera_controller(
     let error = target - actual;
     let p = 200.0 * error;
 
-    let acceleration = time.delta_seconds() * p;
-    *current_speed += time.delta_seconds() * acceleration;
-    camera_transform.translation += time.delta_seconds() * *current_speed;
+    let acceleration = delta_seconds * p;
+    *current_speed += delta_seconds * acceleration;
+    // Moved by big_space in `f64`, as a single frame can cross many grid cells at top speed.
+    velocity.0 = current_speed.as_dvec3();
 
-    if let Some(delta) = mouse.iter().map(|e| e.delta).reduce(|sum, i| sum + i) {
-        camera_target.rotate_local_x(delta.y * -0.003);
-        camera_target.rotate_local_y(delta.x * -0.003);
-    }
+    let delta = std::mem::take(&mut look.0);
+    camera_target.rotate_local_x(delta.y * -0.003);
+    camera_target.rotate_local_y(delta.x * -0.003);
 
     camera_transform.rotation = camera_transform
         .rotation
         .slerp(camera_target.rotation, 0.2)
         .normalize();
 }
+
+/// Pushes the camera back out of anything it flew into.
+pub fn camera_collisions(
+    mut collisions: EventReader<Collision>,
+    mut camera: Query<(Entity, &mut Transform), With<CameraController>>,
+) {
+    let (camera_entity, mut camera_transform) = camera.single_mut();
+    for collision in collisions.iter() {
+        let push = if collision.b == camera_entity {
+            collision.normal * collision.depth
+        } else if collision.a == camera_entity {
+            -collision.normal * collision.depth
+        } else {
+            continue;
+        };
+        camera_transform.translation += push.as_vec3();
+    }
+}
@@ -3,9 +3,15 @@ pub mod camera;
 pub mod post_processing;
 pub mod sunlight;
 
-use bevy::{pbr::PbrPlugin, prelude::*};
+use bevy::{math::DVec3, pbr::PbrPlugin, prelude::*};
 
-use big_space::{FloatingOrigin, FloatingOriginSettings, GridCell};
+use big_space::{
+    collision::{Collider, CollisionPlugin},
+    commands::GridCommandsExt,
+    fixed_timestep::{FixedTimestepPlugin, Interpolated},
+    kinematics::GridVelocity,
+    FloatingOrigin, FloatingOriginSettings,
+};
 use body::{Atmosphere, Body};
 use camera::CameraController;
 use sunlight::Sunlight;
@@ -15,7 +21,6 @@ fn main() {
         .add_plugins(
             DefaultPlugins
                 .build()
-                .disable::<TransformPlugin>()
                 .set(AssetPlugin {
                     watch_for_changes: true,
                     ..default()
@@ -45,6 +50,11 @@ fn main() {
             settings: FloatingOriginSettings::new(10_000.0, 100.0),
             ..default()
         })
+        .add_plugin(FixedTimestepPlugin::<i128>::default())
+        .add_plugin(CollisionPlugin::<i128> {
+            fixed_timestep: true,
+            ..default()
+        })
         // .add_plugin(big_space::debug::FloatingOriginDebugPlugin::<i128>::default())
         .add_plugin(post_processing::PostProcessingPlugin)
         .add_plugin(body::BodyPlugin)
@@ -65,85 +75,92 @@ fn setup(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    commands.spawn((
-        sunlight::SunlightCamera,
-        Camera3dBundle {
-            projection: bevy::render::camera::Projection::Perspective(PerspectiveProjection {
-                fov: 1.5,
+    commands.spawn_at::<i128>(
+        DVec3::new(5.0, 5.0, 9_993_700_226.5),
+        (
+            sunlight::SunlightCamera,
+            Camera3dBundle {
+                projection: bevy::render::camera::Projection::Perspective(PerspectiveProjection {
+                    fov: 1.5,
+                    ..default()
+                }),
+                camera: Camera {
+                    hdr: true,
+                    ..default()
+                },
                 ..default()
-            }),
-            transform: Transform::from_xyz(5.0, 5.0, 226.5),
-            camera: Camera {
-                hdr: true,
+            },
+            UiCameraConfig { show_ui: false },
+            FloatingOrigin,
+            CameraController::new(299_792_458.0 * 50_000_000.0, 100.0),
+            Collider::sphere(1.0),
+            GridVelocity::default(),
+            Interpolated::<i128>::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            bevy::core_pipeline::bloom::BloomSettings {
+                intensity: 0.05,
                 ..default()
             },
-            ..default()
-        },
-        UiCameraConfig { show_ui: false },
-        GridCell::<i128>::new(0, 0, 999_370),
-        FloatingOrigin,
-        CameraController::new(299_792_458.0 * 50_000_000.0, 100.0),
-        #[cfg(not(target_arch = "wasm32"))]
-        bevy::core_pipeline::bloom::BloomSettings {
-            intensity: 0.05,
-            ..default()
-        },
-    ));
+        ),
+    );
 
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Cube { size: 10.0 })),
-            material: materials.add(StandardMaterial {
-                base_color: Color::YELLOW,
-                ..Default::default()
-            }),
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 220.5),
-                rotation: Quat::from_euler(EulerRot::XYZ, 0.5, 0.5, 1.0),
+    commands.spawn_at::<i128>(
+        DVec3::new(0.0, 0.0, 9_993_700_220.5),
+        (
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Cube { size: 10.0 })),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::YELLOW,
+                    ..Default::default()
+                }),
+                transform: Transform::from_rotation(Quat::from_euler(EulerRot::XYZ, 0.5, 0.5, 1.0)),
                 ..default()
             },
-            ..default()
-        },
-        GridCell::<i128>::new(0, 0, 999_370),
-    ));
+            Collider::cuboid(Vec3::splat(5.0)),
+        ),
+    );
 
-    commands.spawn((
-        SpatialBundle::default(),
-        Sunlight {
-            illuminance: 100000.0,
-            color: Color::WHITE,
-        },
-        Body {
-            radius: 250_000_000f32,
-        },
-        materials.add(StandardMaterial {
-            emissive: Color::rgb_linear(5.0, 5.0, 5.0),
-            base_color: Color::rgb_linear(5.0, 5.0, 5.0),
-            unlit: true,
-            ..Default::default()
-        }),
-        GridCell::<i128>::default(),
-    ));
+    commands.spawn_at::<i128>(
+        DVec3::ZERO,
+        (
+            SpatialBundle::default(),
+            Sunlight {
+                illuminance: 100000.0,
+                color: Color::WHITE,
+            },
+            Body {
+                radius: 250_000_000f32,
+            },
+            materials.add(StandardMaterial {
+                emissive: Color::rgb_linear(5.0, 5.0, 5.0),
+                base_color: Color::rgb_linear(5.0, 5.0, 5.0),
+                unlit: true,
+                ..Default::default()
+            }),
+        ),
+    );
 
-    commands.spawn((
-        SpatialBundle::default(),
-        Body {
-            radius: 6_300_000f32,
-        },
-        Atmosphere {
-            sun_dir: -Vec3::Z,
-            surface_radius: 6_300_000f32,
-            radius: 6_400_000f32,
-            gravity: 9.81,
-            surface_temperature: 288.15,
-            surface_pressure: 101.325,
-            molar_mass: 0.02896,
-        },
-        materials.add(StandardMaterial {
-            base_color: Color::BLACK,
-            perceptual_roughness: 1.0,
-            ..Default::default()
-        }),
-        GridCell::<i128>::new(0, 0, 1_000_000),
-    ));
+    commands.spawn_at::<i128>(
+        DVec3::new(0.0, 0.0, 10_000_000_000.0),
+        (
+            SpatialBundle::default(),
+            Body {
+                radius: 6_300_000f32,
+            },
+            Atmosphere {
+                sun_dir: -Vec3::Z,
+                surface_radius: 6_300_000f32,
+                radius: 6_400_000f32,
+                gravity: 9.81,
+                surface_temperature: 288.15,
+                surface_pressure: 101.325,
+                molar_mass: 0.02896,
+            },
+            materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                perceptual_roughness: 1.0,
+                ..Default::default()
+            }),
+        ),
+    );
 }
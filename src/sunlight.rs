@@ -1,4 +1,5 @@
 use bevy::prelude::*;
+use big_space::{grid_transforms::GridTransforms, GridCell};
 
 pub struct SunlightPlugin;
 
@@ -49,25 +50,27 @@ fn directional_light(size: f32) -> DirectionalLightBundle {
 }
 
 pub fn update_sunlight(
-    camera: Query<&GlobalTransform, With<SunlightCamera>>,
-    suns: Query<(&GlobalTransform, &Sunlight)>,
-    mut lights: Query<(&mut Transform, &mut DirectionalLight)>,
+    camera: Query<(Entity, &GlobalTransform), With<SunlightCamera>>,
+    suns: Query<(Entity, &Sunlight)>,
+    grid_transforms: GridTransforms<i128>,
+    mut lights: Query<(&mut Transform, &mut DirectionalLight), Without<GridCell<i128>>>,
 ) {
-    let cam_global = camera.single();
-    let mut distance_sq = f32::MAX;
+    let (cam_entity, cam_global) = camera.single();
+    let mut distance_sq = f64::MAX;
     let mut nearest_sun = None;
-    for (sun_global, sun) in &suns {
-        let sun_dir = sun_global.affine().translation - cam_global.affine().translation;
-        let new_dist = sun_dir.length_squared();
-        if new_dist < distance_sq {
-            distance_sq = new_dist;
-            nearest_sun = Some((sun.to_owned(), Vec3::from(sun_dir)))
+    for (sun_entity, sun) in &suns {
+        if let Some(sun_dir) = grid_transforms.offset(cam_entity, sun_entity) {
+            let new_dist = sun_dir.length_squared();
+            if new_dist < distance_sq {
+                distance_sq = new_dist;
+                nearest_sun = Some((sun.to_owned(), sun_dir))
+            }
         }
     }
     if let Some((sun, sun_dir)) = nearest_sun {
         for (mut light_local, mut directional_light) in lights.iter_mut() {
             let cam_translation = Vec3::from(cam_global.affine().translation);
-            let sun_dir = sun_dir.normalize();
+            let sun_dir = sun_dir.normalize().as_vec3();
             light_local.translation = cam_translation + sun_dir;
             light_local.look_at(cam_translation, Vec3::Y);
             directional_light.illuminance = sun.illuminance / 2.0;